@@ -17,41 +17,39 @@ impl<'a> Assembler<'a> {
         Self { parser }
     }
 
+    /// Writes machine code into `.hack` file next to `file_name`
     pub fn emit(&self, file_name: &Path) -> Result<(), io::Error> {
-        let (file_name, _) = file_name
-            .as_os_str()
-            .to_str()
-            .expect("Ok")
-            .split_once(".")
-            .expect("Must be file with .asm");
-        let mut file = BufWriter::new(File::create(format!("{file_name}.hack"))?);
+        let file = BufWriter::new(File::create(file_name.with_extension("hack"))?);
 
+        self.write(file)
+    }
+
+    /// Writes machine code, one instruction per line, into any writer
+    pub fn write<W: Write>(&self, mut file: W) -> Result<(), io::Error> {
         let instruction_table = InstructionTable::new();
 
         self.parser
             .commands
             .iter()
-            .for_each(|command| match command {
+            .try_for_each(|command| match command {
                 Command::A(value) => {
-                    let number: usize;
-
-                    if value.chars().all(|c| c.is_numeric()) {
-                        number = value.parse::<usize>().expect("Checked to be numeric");
+                    let number = if value.chars().all(|c| c.is_numeric()) {
+                        value.parse::<usize>().expect("Checked to be numeric")
                     } else {
-                        number = *self
+                        *self
                             .parser
                             .symbol_table
                             .table
                             .get(value)
                             .expect("Value must be in symbol table")
-                    }
+                    };
 
                     let binary = format!("0{:015b}\n", number);
-                    file.write_all(&binary.as_bytes())
-                        .expect("Failed to write into file");
+                    file.write_all(binary.as_bytes())
                 }
                 Command::C { dest, comp, jump } => {
-                    let mut result: u16 = 0 | 0b111_00000_0000_0000;
+                    #[allow(clippy::unusual_byte_groupings)]
+                    let mut result: u16 = 0b111_00000_0000_0000;
                     if let Some(dest) = dest {
                         result |= instruction_table.dest.get(dest).expect("Unknown dest");
                     } else {
@@ -85,13 +83,30 @@ impl<'a> Assembler<'a> {
                     }
 
                     file.write_all(format!("{:016b}\n", result).as_bytes())
-                        .expect("Error while writing to file");
                 }
-                _ => {}
-            });
+                _ => Ok(()),
+            })?;
 
         file.flush()?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_machine_code_into_buffer() {
+        let mut buf = vec![];
+        Assembler::new("@2\nD=A\n(LOOP)\n@LOOP\n0;JMP")
+            .write(&mut buf)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "0000000000000010\n1110110000010000\n0000000000000010\n1110101010000111\n"
+        );
+    }
+}
@@ -10,11 +10,11 @@ pub enum Command<'a> {
 }
 
 impl<'a> Command<'a> {
-    pub fn parse_instruction(buf: &str) -> Option<Command> {
+    pub fn parse_instruction(buf: &str) -> Option<Command<'_>> {
         let buf = buf.trim();
 
         match buf {
-            buf if buf.starts_with("//") || buf.len() == 0 => None,
+            buf if buf.starts_with("//") || buf.is_empty() => None,
             buf if buf.starts_with('(') && buf.ends_with(')') => {
                 let label = buf
                     .strip_prefix('(')
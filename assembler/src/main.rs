@@ -11,11 +11,11 @@ fn main() -> Result<(), io::Error> {
     let mut args = env::args();
 
     if args.len() < 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "no filename passed!"));
+        return Err(io::Error::other("no filename passed!"));
     }
 
     if args.len() > 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "too many arguments"));
+        return Err(io::Error::other("too many arguments"));
     }
 
     let file_path = args.nth(1).unwrap();
@@ -22,9 +22,9 @@ impl<'a> Parser<'a> {
         // First pass for getting all labels
         let mut position = 0;
 
-        let _ = file.lines().for_each(|line| {
+        file.lines().for_each(|line| {
             let line = line.trim();
-            if line.len() == 0 || line.starts_with("//") {
+            if line.is_empty() || line.starts_with("//") {
                 return;
             }
 
@@ -45,7 +45,7 @@ impl<'a> Parser<'a> {
         self.commands = file
             .lines()
             .filter_map(|line| {
-                let instruction = Command::parse_instruction(&line);
+                let instruction = Command::parse_instruction(line);
 
                 if let Some(command) = &instruction {
                     match command {
@@ -1,3 +1,5 @@
+#![allow(clippy::unusual_byte_groupings)]
+
 use std::collections::HashMap;
 
 const SYMBOL_TABLE_CAPACITY: usize = 22;
@@ -45,6 +47,12 @@ impl<'a> SymbolTable<'a> {
     }
 }
 
+impl Default for SymbolTable<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct InstructionTable<'a> {
     pub comp: HashMap<&'a str, u16>,
     pub dest: HashMap<&'a str, u16>,
@@ -72,7 +80,7 @@ impl<'a> InstructionTable<'a> {
         comp.insert("A", 0b000_0_110000_000000);
         comp.insert("!D", 0b000_0_001101_000000);
         comp.insert("!A", 0b000_0_110001_000000);
-        comp.insert("-D", 0b000_0_001101_000000);
+        comp.insert("-D", 0b000_0_001111_000000);
         comp.insert("-A", 0b000_0_110011_000000);
         comp.insert("D+1", 0b000_0_011111_000000);
         comp.insert("A+1", 0b000_0_110111_000000);
@@ -108,3 +116,24 @@ impl<'a> InstructionTable<'a> {
         Self { comp, dest, jump }
     }
 }
+
+impl Default for InstructionTable<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negation_and_inversion_differ() {
+        let table = InstructionTable::new();
+
+        assert_eq!(table.comp["-D"], 0b000_0_001111_000000);
+        assert_eq!(table.comp["!D"], 0b000_0_001101_000000);
+        assert_eq!(table.comp["-A"], 0b000_0_110011_000000);
+        assert_eq!(table.comp["!A"], 0b000_0_110001_000000);
+    }
+}
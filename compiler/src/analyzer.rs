@@ -1,35 +1,301 @@
-use std::fs::File;
-use std::io::{BufWriter, Result};
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
 
+use crate::ast::Class;
 use crate::compilation_engine::CompilationEngine;
+use crate::error::SemanticErrors;
+use crate::semantic::Program;
+use crate::tokenizer::{xml_writer, Tokenizer};
+
+/// Output written for every class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// `<name>T.xml` with token stream
+    Tokens,
+    /// `<name>M.xml` with parse tree
+    ParseTree,
+    /// `<name>.vm`
+    Vm,
+    /// `<name>.json` with syntax tree
+    Json,
+}
+
+impl Emit {
+    /// Mode by its `--emit` name: `tokens`, `xml`, `vm` or `json`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tokens" => Some(Emit::Tokens),
+            "xml" => Some(Emit::ParseTree),
+            "vm" => Some(Emit::Vm),
+            "json" => Some(Emit::Json),
+            _ => None,
+        }
+    }
+
+    fn output_name(&self, class: &str) -> String {
+        match self {
+            Emit::Tokens => format!("{class}T.xml"),
+            Emit::ParseTree => format!("{class}M.xml"),
+            Emit::Vm => format!("{class}.vm"),
+            Emit::Json => format!("{class}.json"),
+        }
+    }
+}
+
+pub struct Analyzer {
+    emit: Emit,
+    annotate: bool,
+    compatible: bool,
+    output_dir: Option<PathBuf>,
+}
+
+impl Analyzer {
+    pub fn new(emit: Emit) -> Analyzer {
+        Analyzer {
+            emit,
+            annotate: false,
+            compatible: false,
+            output_dir: None,
+        }
+    }
+
+    /// Annotates identifiers in parse tree XML with symbol table information
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Writes token and parse tree XML in format of reference files of the course
+    pub fn compatible(mut self, compatible: bool) -> Self {
+        self.compatible = compatible;
+        self
+    }
+
+    /// Writes outputs into `dir` instead of next to their sources
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Writes output of every `.jack` file
+    pub fn run<T: AsRef<Path>>(&self, files: &[T]) -> Result<()> {
+        if let Some(dir) = &self.output_dir {
+            fs::create_dir_all(dir)?;
+        }
+        if self.emit == Emit::Vm {
+            return self.compile_program(files);
+        }
+
+        Self::for_each_file(files, |path| {
+            // Output is written only for classes without errors
+            let mut buf = vec![];
+            match self.emit {
+                Emit::Tokens => {
+                    let mut tokenizer = Tokenizer::new(File::open(path)?)?;
+                    xml_writer::write_tokens(&mut tokenizer, &mut buf, self.compatible)?;
+                }
+                Emit::ParseTree => CompilationEngine::new(File::open(path)?)
+                    .annotate(self.annotate)
+                    .compatible(self.compatible)
+                    .compile(&mut buf)?,
+                Emit::Vm => unreachable!("VM code is written by `compile_program`"),
+                Emit::Json => CompilationEngine::new(File::open(path)?).compile_json(&mut buf)?,
+            }
+
+            fs::write(self.output_path(path), buf)
+        })
+    }
+
+    /// Parses every file before writing VM code, so calls between classes can be checked
+    fn compile_program<T: AsRef<Path>>(&self, files: &[T]) -> Result<()> {
+        let parse = |path: &Path| -> Result<Class> {
+            Ok(CompilationEngine::new(File::open(path)?).parse()?)
+        };
+        let parsed: Vec<_> = files.iter().map(|path| parse(path.as_ref())).collect();
+
+        let mut program = Program::new(parsed.iter().flatten());
+        for class in Self::sibling_classes(files) {
+            program.declare(&class);
+        }
+
+        let mut parsed = parsed.into_iter();
+        Self::for_each_file(files, |path| {
+            let class = parsed.next().expect("every file is parsed")?;
+            let errors = program.check(&class);
+            if !errors.is_empty() {
+                return Err(SemanticErrors(errors).into());
+            }
+
+            let mut buf = vec![];
+            CompilationEngine::write_vm(&class, &mut buf)?;
+            fs::write(self.output_path(path), buf)
+        })
+    }
+
+    /// Names of `.jack` and `.vm` files next to `files`, which may be compiled separately
+    fn sibling_classes<T: AsRef<Path>>(files: &[T]) -> Vec<String> {
+        let dirs: BTreeSet<_> = files
+            .iter()
+            .filter_map(|path| path.as_ref().parent())
+            .collect();
+
+        let mut classes = vec![];
+        for dir in dirs {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                let is_class = path
+                    .extension()
+                    .is_some_and(|extension| extension == "jack" || extension == "vm");
+                if let (true, Some(stem)) = (is_class, path.file_stem()) {
+                    classes.push(stem.to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        classes
+    }
+
+    fn output_path(&self, source: &Path) -> PathBuf {
+        let class = source
+            .file_stem()
+            .expect("failed to get file name")
+            .to_string_lossy();
+        let dir = match &self.output_dir {
+            Some(dir) => dir.as_path(),
+            None => source.parent().expect("failed to get parent directory"),
+        };
+
+        dir.join(self.emit.output_name(&class))
+    }
+
+    /// Runs `compile` for every file, reporting failed files and going on with the rest
+    fn for_each_file<T: AsRef<Path>>(
+        files: &[T],
+        mut compile: impl FnMut(&Path) -> Result<()>,
+    ) -> Result<()> {
+        let mut failed = 0;
+        for path in files {
+            let path = path.as_ref();
+            if let Err(err) = compile(path) {
+                eprintln!("error: {}: {err}", path.display());
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{failed} of {} files failed to compile", files.len()),
+            ));
+        }
 
-pub struct Analyzer;
-
-impl<'a> Analyzer {
-    pub fn process<T: AsRef<Path>>(files: &[T]) -> Result<()> {
-        files.iter().for_each(|path| {
-            println!("\n FILE: {:?}", &path.as_ref());
-            let file = File::open(path).expect("failed to open file");
-            let mut compiler = CompilationEngine::new(file);
-
-            let parent_path = path
-                .as_ref()
-                .parent()
-                .expect("failed to get parent directory");
-            let name = path.as_ref().file_stem().expect("failed to get file name");
-            let name = format!(
-                "{}{}",
-                name.to_str().expect("failed to convert path to string"),
-                "M"
-            );
-            let mut output_filename = parent_path.join(name);
-            output_filename.set_extension("xml");
-            let file = File::create(output_filename).expect("failed to create file");
-            let buf = BufWriter::new(file);
-
-            compiler.compile(buf).expect("failed to compile");
-        });
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emits_every_mode_into_output_dir() {
+        let dir = std::env::temp_dir().join(format!("analyzer-emit-{}", std::process::id()));
+        let source = dir.join("Main.jack");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&source, "class Main { function void main() { return; } }").unwrap();
+
+        let out = dir.join("out");
+        for (emit, name) in [
+            (Emit::Tokens, "MainT.xml"),
+            (Emit::ParseTree, "MainM.xml"),
+            (Emit::Vm, "Main.vm"),
+            (Emit::Json, "Main.json"),
+        ] {
+            Analyzer::new(emit)
+                .output_dir(&out)
+                .run(&[&source])
+                .unwrap();
+            assert!(out.join(name).exists(), "{name}");
+            assert!(!dir.join(name).exists(), "{name}");
+        }
+
+        let tokens = fs::read_to_string(out.join("MainT.xml")).unwrap();
+        assert!(tokens
+            .starts_with("<tokens>\n<keyword>class</keyword>\n<identifier>Main</identifier>\n"));
+        assert!(tokens.ends_with("<symbol>}</symbol>\n</tokens>\n"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compiles_remaining_files_after_error() {
+        let dir = std::env::temp_dir().join(format!("analyzer-errors-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let sources = [
+            ("Bad.jack", "class Bad { function void f() { let = 1; } }"),
+            (
+                "Main.jack",
+                "class Main { function void main() { return; } }",
+            ),
+        ];
+        let paths: Vec<_> = sources
+            .iter()
+            .map(|(name, source)| {
+                let path = dir.join(name);
+                fs::write(&path, source).unwrap();
+                path
+            })
+            .collect();
+
+        let err = Analyzer::new(Emit::Vm).run(&paths).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 files failed to compile");
+        assert!(!dir.join("Bad.vm").exists());
+        assert!(fs::read_to_string(dir.join("Main.vm"))
+            .unwrap()
+            .starts_with("function Main.main 0"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checks_calls_between_files() {
+        let dir = std::env::temp_dir().join(format!("analyzer-semantic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let sources = [
+            (
+                "Main.jack",
+                "class Main { function void main() { do Point.new(1); return; } }",
+            ),
+            (
+                "Point.jack",
+                "class Point { constructor Point new(int x, int y) { return this; } }",
+            ),
+        ];
+        let paths: Vec<_> = sources
+            .iter()
+            .map(|(name, source)| {
+                let path = dir.join(name);
+                fs::write(&path, source).unwrap();
+                path
+            })
+            .collect();
+
+        let err = Analyzer::new(Emit::Vm).run(&paths).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 files failed to compile");
+        assert!(!dir.join("Main.vm").exists());
+        assert!(dir.join("Point.vm").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
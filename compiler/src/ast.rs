@@ -0,0 +1,227 @@
+//! Typed syntax tree of a Jack class, built by `CompilationEngine::parse`.
+//!
+//! Nodes mirror the nonterminals of the Jack grammar. Declarations, statements,
+//! expressions and terms carry the `Span` of their first token
+use core::fmt;
+
+use crate::tokenizer::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    pub name: String,
+    pub vars: Vec<ClassVarDec>,
+    pub subroutines: Vec<SubroutineDec>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassVarKind {
+    Static,
+    Field,
+}
+
+/// `static int x, y;` or `field int x, y;`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassVarDec {
+    pub kind: ClassVarKind,
+    pub type_name: Type,
+    pub names: Vec<String>,
+    pub span: Span,
+}
+
+/// Type of variable or return value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Char => write!(f, "char"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Class(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineDec {
+    pub kind: SubroutineKind,
+    /// `None` for `void`
+    pub return_type: Option<Type>,
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub vars: Vec<VarDec>,
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub type_name: Type,
+    pub name: String,
+    pub span: Span,
+}
+
+/// `var int x, y;`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDec {
+    pub type_name: Type,
+    pub names: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatementKind {
+    Let(LetStatement),
+    If(IfStatement),
+    While(WhileStatement),
+    Do(SubroutineCall),
+    Return(Option<Expression>),
+}
+
+/// `let name = value;` or `let name[index] = value;`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LetStatement {
+    pub name: String,
+    pub index: Option<Expression>,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfStatement {
+    pub condition: Expression,
+    pub statements: Vec<Statement>,
+    /// Statements of `else` branch, if present
+    pub otherwise: Option<Vec<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhileStatement {
+    pub condition: Expression,
+    pub statements: Vec<Statement>,
+}
+
+/// `term (op term)*`, evaluated left to right since Jack has no operator priority
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    pub term: Term,
+    pub operations: Vec<(BinaryOp, Term)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            '+' => Some(BinaryOp::Add),
+            '-' => Some(BinaryOp::Sub),
+            '*' => Some(BinaryOp::Mul),
+            '/' => Some(BinaryOp::Div),
+            '&' => Some(BinaryOp::And),
+            '|' => Some(BinaryOp::Or),
+            '<' => Some(BinaryOp::Lt),
+            '>' => Some(BinaryOp::Gt),
+            '=' => Some(BinaryOp::Eq),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static char {
+        match self {
+            BinaryOp::Add => &'+',
+            BinaryOp::Sub => &'-',
+            BinaryOp::Mul => &'*',
+            BinaryOp::Div => &'/',
+            BinaryOp::And => &'&',
+            BinaryOp::Or => &'|',
+            BinaryOp::Lt => &'<',
+            BinaryOp::Gt => &'>',
+            BinaryOp::Eq => &'=',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-`
+    Neg,
+    /// `~`
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static char {
+        match self {
+            UnaryOp::Neg => &'-',
+            UnaryOp::Not => &'~',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordConst {
+    True,
+    False,
+    Null,
+    This,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TermKind {
+    IntConst(u16),
+    StringConst(String),
+    Keyword(KeywordConst),
+    Var(String),
+    /// `name[index]`
+    Index(String, Box<Expression>),
+    Call(SubroutineCall),
+    Parenthesized(Box<Expression>),
+    Unary(UnaryOp, Box<Term>),
+}
+
+/// `name(args)`, `var.name(args)` or `Class.name(args)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineCall {
+    /// Variable or class name before `.`
+    pub receiver: Option<String>,
+    pub name: String,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
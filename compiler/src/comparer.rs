@@ -0,0 +1,146 @@
+//! Compares XML output with reference files of the course.
+//!
+//! Whitespace is ignored, as TextComparer of the course does, so
+//! `<keyword>class</keyword>` equals `<keyword> class </keyword>`
+use std::{fmt, fs, io, path::Path};
+
+/// Tag or text between tags, with whitespace removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlToken {
+    pub text: String,
+    /// Line where token starts
+    pub line: usize,
+}
+
+/// First differing token, `None` means end of file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub expected: Option<XmlToken>,
+    pub found: Option<XmlToken>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |token: &Option<XmlToken>| match token {
+            Some(token) => format!("`{}` on line {}", token.text, token.line),
+            None => "end of file".to_owned(),
+        };
+
+        write!(
+            f,
+            "expected {}, found {}",
+            describe(&self.expected),
+            describe(&self.found)
+        )
+    }
+}
+
+/// Splits XML into tags and texts between them
+pub fn tokens(source: &str) -> Vec<XmlToken> {
+    let mut tokens = vec![];
+    let mut current: Option<XmlToken> = None;
+    let mut line = 1;
+
+    for c in source.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+
+        // Text ends before tag and tag ends with `>`
+        if c == '<' {
+            tokens.extend(current.take());
+        }
+        let token = current.get_or_insert_with(|| XmlToken {
+            text: String::new(),
+            line,
+        });
+        token.text.push(c);
+        if c == '>' {
+            tokens.extend(current.take());
+        }
+    }
+    tokens.extend(current);
+
+    tokens
+}
+
+/// Returns first token where `found` differs from `expected`
+pub fn compare(expected: &str, found: &str) -> Option<Mismatch> {
+    let expected = tokens(expected);
+    let found = tokens(found);
+
+    let index = (0..expected.len().max(found.len())).find(|index| {
+        expected.get(*index).map(|t| &t.text) != found.get(*index).map(|t| &t.text)
+    })?;
+
+    Some(Mismatch {
+        expected: expected.get(index).cloned(),
+        found: found.get(index).cloned(),
+    })
+}
+
+pub fn compare_files(expected: &Path, found: &Path) -> io::Result<Option<Mismatch>> {
+    Ok(compare(
+        &fs::read_to_string(expected)?,
+        &fs::read_to_string(found)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_whitespace() {
+        let reference = "<class>\r\n  <keyword> class </keyword>\r\n  <stringConstant> a b </stringConstant>\r\n</class>\r\n";
+        let output =
+            "<class>\n<keyword>class</keyword>\n    <stringConstant>ab</stringConstant></class>";
+
+        assert_eq!(compare(reference, output), None);
+        assert_eq!(
+            tokens(reference)
+                .iter()
+                .map(|token| token.text.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "<class>",
+                "<keyword>",
+                "class",
+                "</keyword>",
+                "<stringConstant>",
+                "ab",
+                "</stringConstant>",
+                "</class>"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_first_differing_token() {
+        let mismatch = compare(
+            "<tokens>\n<symbol> &lt; </symbol>\n<symbol> ; </symbol>\n</tokens>",
+            "<tokens>\n\n<symbol> < </symbol>\n<symbol> ; </symbol>\n</tokens>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mismatch.to_string(),
+            "expected `&lt;` on line 2, found `<` on line 3"
+        );
+    }
+
+    #[test]
+    fn reports_missing_tokens() {
+        let mismatch = compare("<a> x </a>", "<a> x </a> <b>").unwrap();
+        assert_eq!(mismatch.expected, None);
+        assert_eq!(
+            mismatch.to_string(),
+            "expected end of file, found `<b>` on line 1"
+        );
+
+        assert!(compare("<a>\n</a>", "<a>").unwrap().found.is_none());
+    }
+}
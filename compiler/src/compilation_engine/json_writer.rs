@@ -0,0 +1,483 @@
+use std::io::{Result, Write};
+
+use translator::json;
+
+use crate::{ast::*, tokenizer::Span};
+
+use super::writer::CompilationWriter;
+
+/// Writes syntax tree as JSON, every node is an object and
+/// statements and terms are told apart by their `kind`
+pub struct JsonWriter<'a, W: Write> {
+    writer: &'a mut W,
+    /// Open objects and arrays, `true` once they have an element
+    stack: Vec<bool>,
+    /// Key is written, so next value goes right after it
+    after_key: bool,
+}
+
+impl<'a, W: Write> JsonWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> JsonWriter<'a, W> {
+        JsonWriter {
+            writer,
+            stack: vec![],
+            after_key: false,
+        }
+    }
+
+    /// Writes `,` and new line before next element of current object or array
+    fn separate(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.after_key) {
+            return Ok(());
+        }
+        let Some(has_elements) = self.stack.last_mut() else {
+            return Ok(());
+        };
+
+        if *has_elements {
+            self.writer.write_all(b",")?;
+        }
+        *has_elements = true;
+        write!(self.writer, "\n{:1$}", "", self.stack.len() * 2)
+    }
+
+    fn open(&mut self, bracket: char) -> Result<()> {
+        self.separate()?;
+        write!(self.writer, "{bracket}")?;
+        self.stack.push(false);
+
+        Ok(())
+    }
+
+    fn close(&mut self, bracket: char) -> Result<()> {
+        if self.stack.pop() == Some(true) {
+            write!(self.writer, "\n{:1$}", "", self.stack.len() * 2)?;
+        }
+        write!(self.writer, "{bracket}")
+    }
+
+    fn key(&mut self, key: &str) -> Result<()> {
+        self.separate()?;
+        write!(self.writer, "\"{key}\": ")?;
+        self.after_key = true;
+
+        Ok(())
+    }
+
+    /// Writes `"key": value`, where `value` is already JSON
+    fn field(&mut self, key: &str, value: &str) -> Result<()> {
+        self.key(key)?;
+        self.separate()?;
+        self.writer.write_all(value.as_bytes())
+    }
+
+    fn string_field(&mut self, key: &str, value: &str) -> Result<()> {
+        self.field(key, &json::string(value))
+    }
+
+    /// `"line"` and `"column"` of node
+    fn span(&mut self, span: Span) -> Result<()> {
+        self.field("line", &span.line.to_string())?;
+        self.field("column", &span.column.to_string())
+    }
+
+    fn names(&mut self, names: &[String]) -> Result<()> {
+        let names: Vec<String> = names.iter().map(|name| json::string(name)).collect();
+        self.field("names", &format!("[{}]", names.join(", ")))
+    }
+
+    fn optional_expression(&mut self, key: &str, expression: Option<&Expression>) -> Result<()> {
+        match expression {
+            Some(expression) => {
+                self.key(key)?;
+                self.write_expression(expression)
+            }
+            None => self.field(key, "null"),
+        }
+    }
+}
+
+impl<'a, W: Write> CompilationWriter for JsonWriter<'a, W> {
+    fn write_class(&mut self, class: &Class) -> Result<()> {
+        self.open('{')?;
+        self.string_field("name", &class.name)?;
+        self.span(class.span)?;
+
+        self.key("vars")?;
+        self.open('[')?;
+        for declaration in &class.vars {
+            self.write_class_var_declaration(declaration)?;
+        }
+        self.close(']')?;
+
+        self.key("subroutines")?;
+        self.open('[')?;
+        for subroutine in &class.subroutines {
+            self.write_subroutine_declaration(subroutine)?;
+        }
+        self.close(']')?;
+
+        self.close('}')?;
+        writeln!(self.writer)
+    }
+
+    fn write_class_var_declaration(&mut self, declaration: &ClassVarDec) -> Result<()> {
+        self.open('{')?;
+        self.string_field(
+            "kind",
+            match declaration.kind {
+                ClassVarKind::Static => "static",
+                ClassVarKind::Field => "field",
+            },
+        )?;
+        self.string_field("type", &declaration.type_name.to_string())?;
+        self.names(&declaration.names)?;
+        self.span(declaration.span)?;
+        self.close('}')
+    }
+
+    fn write_subroutine_declaration(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        self.open('{')?;
+        self.string_field(
+            "kind",
+            match subroutine.kind {
+                SubroutineKind::Constructor => "constructor",
+                SubroutineKind::Function => "function",
+                SubroutineKind::Method => "method",
+            },
+        )?;
+        match &subroutine.return_type {
+            Some(type_name) => self.string_field("returnType", &type_name.to_string())?,
+            None => self.field("returnType", "null")?,
+        }
+        self.string_field("name", &subroutine.name)?;
+        self.span(subroutine.span)?;
+
+        self.key("parameters")?;
+        self.write_parameter_list(&subroutine.parameters)?;
+        self.write_subroutine_body(subroutine)?;
+
+        self.close('}')
+    }
+
+    fn write_parameter_list(&mut self, parameters: &[Parameter]) -> Result<()> {
+        self.open('[')?;
+        for parameter in parameters {
+            self.open('{')?;
+            self.string_field("type", &parameter.type_name.to_string())?;
+            self.string_field("name", &parameter.name)?;
+            self.span(parameter.span)?;
+            self.close('}')?;
+        }
+        self.close(']')
+    }
+
+    /// Writes `vars` and `statements` into object of subroutine
+    fn write_subroutine_body(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        self.key("vars")?;
+        self.open('[')?;
+        for declaration in &subroutine.vars {
+            self.write_var_declaration(declaration)?;
+        }
+        self.close(']')?;
+
+        self.key("statements")?;
+        self.write_statements(&subroutine.statements)
+    }
+
+    fn write_var_declaration(&mut self, declaration: &VarDec) -> Result<()> {
+        self.open('{')?;
+        self.string_field("type", &declaration.type_name.to_string())?;
+        self.names(&declaration.names)?;
+        self.span(declaration.span)?;
+        self.close('}')
+    }
+
+    fn write_statements(&mut self, statements: &[Statement]) -> Result<()> {
+        self.open('[')?;
+        for statement in statements {
+            self.write_statement(statement)?;
+        }
+        self.close(']')
+    }
+
+    /// Object of statement holds fields of its kind followed by its span
+    fn write_statement(&mut self, statement: &Statement) -> Result<()> {
+        self.open('{')?;
+        match &statement.kind {
+            StatementKind::Let(statement) => self.write_let_statement(statement)?,
+            StatementKind::If(statement) => self.write_if_statement(statement)?,
+            StatementKind::While(statement) => self.write_while_statement(statement)?,
+            StatementKind::Do(call) => self.write_do_statement(call)?,
+            StatementKind::Return(value) => self.write_return_statement(value.as_ref())?,
+        }
+        self.span(statement.span)?;
+        self.close('}')
+    }
+
+    fn write_let_statement(&mut self, statement: &LetStatement) -> Result<()> {
+        self.string_field("kind", "let")?;
+        self.string_field("name", &statement.name)?;
+        self.optional_expression("index", statement.index.as_ref())?;
+        self.key("value")?;
+        self.write_expression(&statement.value)
+    }
+
+    fn write_if_statement(&mut self, statement: &IfStatement) -> Result<()> {
+        self.string_field("kind", "if")?;
+        self.key("condition")?;
+        self.write_expression(&statement.condition)?;
+        self.key("statements")?;
+        self.write_statements(&statement.statements)?;
+
+        self.key("else")?;
+        match &statement.otherwise {
+            Some(statements) => self.write_statements(statements),
+            None => {
+                self.separate()?;
+                self.writer.write_all(b"null")
+            }
+        }
+    }
+
+    fn write_while_statement(&mut self, statement: &WhileStatement) -> Result<()> {
+        self.string_field("kind", "while")?;
+        self.key("condition")?;
+        self.write_expression(&statement.condition)?;
+        self.key("statements")?;
+        self.write_statements(&statement.statements)
+    }
+
+    fn write_do_statement(&mut self, call: &SubroutineCall) -> Result<()> {
+        self.string_field("kind", "do")?;
+        self.key("call")?;
+        self.open('{')?;
+        self.write_subroutine_call(call)?;
+        self.close('}')
+    }
+
+    fn write_return_statement(&mut self, value: Option<&Expression>) -> Result<()> {
+        self.string_field("kind", "return")?;
+        self.optional_expression("value", value)
+    }
+
+    fn write_expression(&mut self, expression: &Expression) -> Result<()> {
+        self.open('{')?;
+        self.key("term")?;
+        self.write_term(&expression.term)?;
+
+        self.key("operations")?;
+        self.open('[')?;
+        for (op, term) in &expression.operations {
+            self.open('{')?;
+            self.string_field("op", &op.symbol().to_string())?;
+            self.key("term")?;
+            self.write_term(term)?;
+            self.close('}')?;
+        }
+        self.close(']')?;
+
+        self.span(expression.span)?;
+        self.close('}')
+    }
+
+    fn write_term(&mut self, term: &Term) -> Result<()> {
+        self.open('{')?;
+        match &term.kind {
+            TermKind::IntConst(value) => {
+                self.string_field("kind", "int")?;
+                self.field("value", &value.to_string())?;
+            }
+            TermKind::StringConst(string) => {
+                self.string_field("kind", "string")?;
+                self.string_field("value", string)?;
+            }
+            TermKind::Keyword(keyword) => {
+                self.string_field("kind", "keyword")?;
+                self.string_field(
+                    "value",
+                    match keyword {
+                        KeywordConst::True => "true",
+                        KeywordConst::False => "false",
+                        KeywordConst::Null => "null",
+                        KeywordConst::This => "this",
+                    },
+                )?;
+            }
+            TermKind::Var(name) => {
+                self.string_field("kind", "var")?;
+                self.string_field("name", name)?;
+            }
+            TermKind::Index(name, index) => {
+                self.string_field("kind", "index")?;
+                self.string_field("name", name)?;
+                self.key("index")?;
+                self.write_expression(index)?;
+            }
+            TermKind::Call(call) => {
+                self.string_field("kind", "call")?;
+                self.write_subroutine_call(call)?;
+            }
+            TermKind::Parenthesized(expression) => {
+                self.string_field("kind", "parenthesized")?;
+                self.key("expression")?;
+                self.write_expression(expression)?;
+            }
+            TermKind::Unary(op, operand) => {
+                self.string_field("kind", "unary")?;
+                self.string_field("op", &op.symbol().to_string())?;
+                self.key("term")?;
+                self.write_term(operand)?;
+            }
+        }
+        self.span(term.span)?;
+        self.close('}')
+    }
+
+    /// Writes `receiver`, `name` and `arguments` into object of call
+    fn write_subroutine_call(&mut self, call: &SubroutineCall) -> Result<()> {
+        match &call.receiver {
+            Some(receiver) => self.string_field("receiver", receiver)?,
+            None => self.field("receiver", "null")?,
+        }
+        self.string_field("name", &call.name)?;
+        self.key("arguments")?;
+        self.write_expression_list(&call.arguments)
+    }
+
+    fn write_expression_list(&mut self, expressions: &[Expression]) -> Result<()> {
+        self.open('[')?;
+        for expression in expressions {
+            self.write_expression(expression)?;
+        }
+        self.close(']')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compilation_engine::CompilationEngine;
+
+    fn json(source: &str) -> String {
+        let mut buf = vec![];
+        CompilationEngine::new(source.as_bytes())
+            .compile_json(&mut buf)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn writes_syntax_tree() {
+        let json = json(
+            "class Main {\n  static int n;\n  function void main(int a) {\n    var String s;\n    let s = \"C:\\dir\";\n    do Output.printInt(-a + 1);\n    return;\n  }\n}",
+        );
+
+        assert_eq!(
+            json,
+            r#"{
+  "name": "Main",
+  "line": 1,
+  "column": 1,
+  "vars": [
+    {
+      "kind": "static",
+      "type": "int",
+      "names": ["n"],
+      "line": 2,
+      "column": 3
+    }
+  ],
+  "subroutines": [
+    {
+      "kind": "function",
+      "returnType": null,
+      "name": "main",
+      "line": 3,
+      "column": 3,
+      "parameters": [
+        {
+          "type": "int",
+          "name": "a",
+          "line": 3,
+          "column": 22
+        }
+      ],
+      "vars": [
+        {
+          "type": "String",
+          "names": ["s"],
+          "line": 4,
+          "column": 5
+        }
+      ],
+      "statements": [
+        {
+          "kind": "let",
+          "name": "s",
+          "index": null,
+          "value": {
+            "term": {
+              "kind": "string",
+              "value": "C:\\dir",
+              "line": 5,
+              "column": 13
+            },
+            "operations": [],
+            "line": 5,
+            "column": 13
+          },
+          "line": 5,
+          "column": 5
+        },
+        {
+          "kind": "do",
+          "call": {
+            "receiver": "Output",
+            "name": "printInt",
+            "arguments": [
+              {
+                "term": {
+                  "kind": "unary",
+                  "op": "-",
+                  "term": {
+                    "kind": "var",
+                    "name": "a",
+                    "line": 6,
+                    "column": 25
+                  },
+                  "line": 6,
+                  "column": 24
+                },
+                "operations": [
+                  {
+                    "op": "+",
+                    "term": {
+                      "kind": "int",
+                      "value": 1,
+                      "line": 6,
+                      "column": 29
+                    }
+                  }
+                ],
+                "line": 6,
+                "column": 24
+              }
+            ]
+          },
+          "line": 6,
+          "column": 5
+        },
+        {
+          "kind": "return",
+          "value": null,
+          "line": 7,
+          "column": 5
+        }
+      ]
+    }
+  ]
+}
+"#
+        );
+    }
+}
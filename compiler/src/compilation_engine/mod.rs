@@ -1,8 +1,13 @@
+mod json_writer;
+mod parser;
+mod vm_writer;
 mod writer;
 
+use json_writer::JsonWriter;
+use vm_writer::VmWriter;
 use writer::{CompilationWriter, XmlWriter};
 
-use crate::tokenizer::Tokenizer;
+use crate::{ast::Class, error::SyntaxError, tokenizer::Tokenizer};
 
 pub enum NonTerminals {
     Class,
@@ -22,37 +27,79 @@ pub enum NonTerminals {
     ExpressionList,
 }
 
-// struct Class {
-//     name: String,
-//     vars: Vec<()>,
-//     subroutines: Vec<()>,
-// }
+impl NonTerminals {
+    /// Tag name in parse tree XML
+    pub fn name(&self) -> &'static str {
+        match self {
+            NonTerminals::Class => "class",
+            NonTerminals::ClassVarDec => "classVarDec",
+            NonTerminals::SubroutineDec => "subroutineDec",
+            NonTerminals::ParameterList => "parameterList",
+            NonTerminals::SubroutineBody => "subroutineBody",
+            NonTerminals::VarDec => "varDec",
+            NonTerminals::Statements => "statements",
+            NonTerminals::WhileStatement => "whileStatement",
+            NonTerminals::IfStatement => "ifStatement",
+            NonTerminals::ReturnStatement => "returnStatement",
+            NonTerminals::LetStatement => "letStatement",
+            NonTerminals::DoStatement => "doStatement",
+            NonTerminals::Expression => "expression",
+            NonTerminals::Term => "term",
+            NonTerminals::ExpressionList => "expressionList",
+        }
+    }
+}
 
 /// Recursive top-down parser
-pub struct CompilationEngine<T: std::io::Read> {
-    tokenizer: Tokenizer<T>,
+pub struct CompilationEngine {
+    tokenizer: Tokenizer,
+    annotate: bool,
+    compatible: bool,
+    /// Errors parser has recovered from
+    errors: Vec<SyntaxError>,
 }
 
-impl<T: std::io::Read> CompilationEngine<T> {
-    pub fn new(file: T) -> CompilationEngine<T> {
+impl CompilationEngine {
+    pub fn new<T: std::io::Read>(file: T) -> CompilationEngine {
         CompilationEngine {
             tokenizer: Tokenizer::new(file).expect("failed to create tokenizer"),
+            annotate: false,
+            compatible: false,
+            errors: vec![],
         }
     }
 
+    /// Annotates identifiers in XML output with symbol table information
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Writes XML in format of reference files of the course
+    pub fn compatible(mut self, compatible: bool) -> Self {
+        self.compatible = compatible;
+        self
+    }
+
+    /// Parses class and writes its parse tree as XML
     pub fn compile<W: std::io::Write>(&mut self, mut writer: W) -> std::io::Result<()> {
-        let mut output_writer = XmlWriter::new(self, &mut writer);
-        output_writer.write_class()?;
-
-        // let name = self
-        //     .tokenizer
-        //     .advance()
-        //     .expect("input ended unexpectedly, class name expected")?;
-        // let name = match name {
-        //     Token::StringConst(name) => name,
-        //     _ => panic!("expected class name, found {:?}", name),
-        // };
-
-        Ok(())
+        let class = self.parse()?;
+
+        XmlWriter::new(&mut writer)
+            .annotate(self.annotate)
+            .compatible(self.compatible)
+            .write_class(&class)
+    }
+
+    /// Compiles parsed class into VM code
+    pub fn write_vm<W: std::io::Write>(class: &Class, mut writer: W) -> std::io::Result<()> {
+        VmWriter::new(&mut writer).write_class(class)
+    }
+
+    /// Parses class and writes its syntax tree as JSON
+    pub fn compile_json<W: std::io::Write>(&mut self, mut writer: W) -> std::io::Result<()> {
+        let class = self.parse()?;
+
+        JsonWriter::new(&mut writer).write_class(&class)
     }
 }
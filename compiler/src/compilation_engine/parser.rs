@@ -0,0 +1,940 @@
+use crate::{
+    ast::*,
+    error::{SyntaxError, SyntaxErrors},
+    tokenizer::{Keyword, Span, Token},
+};
+
+use super::CompilationEngine;
+
+type Result<T> = std::result::Result<T, SyntaxError>;
+
+/// Alternatives reported when class body is not closed
+const CLASS_MEMBERS: [&str; 3] = ["`static`", "`field`", "subroutine declaration"];
+
+fn is_class_member_start(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Static
+                | Keyword::Field
+                | Keyword::Constructor
+                | Keyword::Function
+                | Keyword::Method
+        )
+    )
+}
+
+fn is_statement_start(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(Keyword::Let | Keyword::If | Keyword::While | Keyword::Do | Keyword::Return)
+    )
+}
+
+impl CompilationEngine {
+    /// Parses whole class from input, returning every syntax error found in it
+    pub fn parse(&mut self) -> std::result::Result<Class, SyntaxErrors> {
+        let class = self.parse_class();
+        if let Err(err) = &class {
+            self.report(err.clone());
+        }
+
+        let errors = std::mem::take(&mut self.errors);
+        match class {
+            Ok(class) if errors.is_empty() => Ok(class),
+            _ => Err(SyntaxErrors(errors)),
+        }
+    }
+
+    /// Records error parser recovers from, skipping repeated errors at the same place
+    fn report(&mut self, err: SyntaxError) {
+        if self.errors.last().map(|last| last.span) != Some(err.span) {
+            self.errors.push(err);
+        }
+    }
+
+    /// Skips tokens after error up to end of statement: past `;`,
+    /// or before `}` or next statement. Blocks opened meanwhile are skipped whole
+    fn skip_statement(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokenizer.peek_token() {
+            match token {
+                Ok(Token::Symbol(&'{')) => depth += 1,
+                Ok(Token::Symbol(&'}')) if depth == 0 => return,
+                Ok(Token::Symbol(&'}')) => depth -= 1,
+                Ok(Token::Symbol(&';')) if depth == 0 => {
+                    self.tokenizer.advance();
+                    return;
+                }
+                Ok(token) if depth == 0 && is_statement_start(&token) => return,
+                Ok(_) => {}
+                Err(err) => self.report(err),
+            }
+            self.tokenizer.advance();
+        }
+    }
+
+    /// Skips tokens after error up to next class member or closing `}` of class
+    fn skip_class_member(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokenizer.peek_token() {
+            match token {
+                Ok(Token::Symbol(&'{')) => depth += 1,
+                Ok(Token::Symbol(&'}')) if depth == 0 => return,
+                Ok(Token::Symbol(&'}')) => depth -= 1,
+                Ok(token) if depth == 0 && is_class_member_start(&token) => return,
+                Ok(_) => {}
+                Err(err) => self.report(err),
+            }
+            self.tokenizer.advance();
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Class> {
+        self.next_matching(&["`class`"], |token| match token {
+            Token::Keyword(Keyword::Class) => Ok(()),
+            token => Err(token),
+        })?;
+        let span = self.span();
+
+        let name = self.identifier()?;
+        self.expect_symbol(&'{')?;
+
+        let mut vars = vec![];
+        let mut subroutines = vec![];
+        let members = loop {
+            let members = if subroutines.is_empty() {
+                &CLASS_MEMBERS[..]
+            } else {
+                &CLASS_MEMBERS[2..]
+            };
+
+            let member = match self.peek_token() {
+                Ok(None | Some(Token::Symbol(&'}'))) => break members,
+                Ok(Some(token @ Token::Keyword(Keyword::Static | Keyword::Field))) => {
+                    // Variables after subroutines are reported, but still parsed
+                    if !subroutines.is_empty() {
+                        let err = self.unexpected(Some(&token), &[members[0], "`}`"]);
+                        self.report(err);
+                    }
+                    self.parse_class_var_declaration().map(|var| vars.push(var))
+                }
+                Ok(Some(token)) if is_class_member_start(&token) => self
+                    .parse_subroutine_declaration()
+                    .map(|subroutine| subroutines.push(subroutine)),
+                _ => self.expect_symbol_or(&'}', members),
+            };
+
+            if let Err(err) = member {
+                self.report(err);
+                self.skip_class_member();
+            }
+        };
+        self.expect_symbol_or(&'}', members)?;
+
+        // File holds single class, anything after it is reported once
+        match self.tokenizer.peek_token() {
+            Some(Ok(token)) => {
+                let err = self.unexpected(Some(&token), &["end of file"]);
+                self.report(err);
+            }
+            Some(Err(err)) => self.report(err),
+            None => {}
+        }
+
+        Ok(Class {
+            name,
+            vars,
+            subroutines,
+            span,
+        })
+    }
+
+    pub(crate) fn expect(&self, token: &Token, expected_token: Token) -> Result<()> {
+        if token != &expected_token {
+            return Err(self.unexpected(Some(token), &[&format!("`{expected_token}`")]));
+        }
+
+        Ok(())
+    }
+
+    /// Error at last token, `None` means end of file
+    fn unexpected(&self, found: Option<&Token>, expected: &[&str]) -> SyntaxError {
+        let found = match found {
+            Some(token) => format!("`{token}`"),
+            None => "end of file".to_owned(),
+        };
+
+        self.tokenizer
+            .error(expected.iter().map(|e| e.to_string()).collect(), found)
+    }
+
+    /// Takes next token and converts it with `accept`, which gives token back
+    /// if it is not one of `expected`. Unexpected token is left for error recovery
+    fn next_matching<R>(
+        &mut self,
+        expected: &[&str],
+        accept: impl FnOnce(Token) -> std::result::Result<R, Token>,
+    ) -> Result<R> {
+        match self.tokenizer.peek_token() {
+            Some(Ok(token)) => match accept(token) {
+                Ok(result) => {
+                    self.tokenizer.advance();
+                    Ok(result)
+                }
+                Err(token) => Err(self.unexpected(Some(&token), expected)),
+            },
+            Some(Err(err)) => {
+                self.tokenizer.advance();
+                Err(err)
+            }
+            None => Err(self.unexpected(None, expected)),
+        }
+    }
+
+    fn next_token(&mut self, expected: &[&str]) -> Result<Token> {
+        self.next_matching(expected, Ok)
+    }
+
+    fn peek_token(&mut self) -> Result<Option<Token>> {
+        self.tokenizer.peek_token().transpose()
+    }
+
+    fn span(&self) -> Span {
+        self.tokenizer.span()
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static char) -> Result<()> {
+        self.expect_symbol_or(symbol, &[])
+    }
+
+    /// Expects `symbol`, reporting `alternatives` that could come before it on error
+    fn expect_symbol_or(&mut self, symbol: &'static char, alternatives: &[&str]) -> Result<()> {
+        let quoted = format!("`{symbol}`");
+        let mut expected = alternatives.to_vec();
+        expected.push(&quoted);
+
+        self.next_matching(&expected, |token| match token {
+            Token::Symbol(next) if next == symbol => Ok(()),
+            token => Err(token),
+        })
+    }
+
+    /// Consumes next token if it is given symbol
+    fn next_if_symbol(&mut self, symbol: char) -> Result<bool> {
+        let found = matches!(self.peek_token()?, Some(Token::Symbol(next)) if *next == symbol);
+        if found {
+            self.tokenizer.advance();
+        }
+
+        Ok(found)
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        self.next_matching(&["identifier"], |token| match token {
+            Token::Identifier(name) => Ok(name),
+            token => Err(token),
+        })
+    }
+
+    /// `int`, `char`, `boolean` or class name
+    fn parse_type(&mut self) -> Result<Type> {
+        self.next_matching(&["type"], |token| match token {
+            Token::Keyword(Keyword::Int) => Ok(Type::Int),
+            Token::Keyword(Keyword::Char) => Ok(Type::Char),
+            Token::Keyword(Keyword::Boolean) => Ok(Type::Boolean),
+            Token::Identifier(name) => Ok(Type::Class(name)),
+            token => Err(token),
+        })
+    }
+
+    /// `name (, name)* ;`
+    fn parse_names(&mut self) -> Result<Vec<String>> {
+        let mut names = vec![self.identifier()?];
+        while self.next_if_symbol(',')? {
+            names.push(self.identifier()?);
+        }
+        self.expect_symbol_or(&';', &["`,`"])?;
+
+        Ok(names)
+    }
+
+    fn parse_class_var_declaration(&mut self) -> Result<ClassVarDec> {
+        let kind = self.next_matching(&CLASS_MEMBERS[..2], |token| match token {
+            Token::Keyword(Keyword::Static) => Ok(ClassVarKind::Static),
+            Token::Keyword(Keyword::Field) => Ok(ClassVarKind::Field),
+            token => Err(token),
+        })?;
+        let span = self.span();
+        let type_name = self.parse_type()?;
+
+        Ok(ClassVarDec {
+            kind,
+            type_name,
+            names: self.parse_names()?,
+            span,
+        })
+    }
+
+    fn parse_subroutine_declaration(&mut self) -> Result<SubroutineDec> {
+        let kind = self.next_matching(&CLASS_MEMBERS[2..], |token| match token {
+            Token::Keyword(Keyword::Constructor) => Ok(SubroutineKind::Constructor),
+            Token::Keyword(Keyword::Function) => Ok(SubroutineKind::Function),
+            Token::Keyword(Keyword::Method) => Ok(SubroutineKind::Method),
+            token => Err(token),
+        })?;
+        let span = self.span();
+
+        let return_type = if self.peek_token()? == Some(Token::Keyword(Keyword::Void)) {
+            self.tokenizer.advance();
+            None
+        } else {
+            Some(self.parse_type().map_err(|mut err| {
+                err.expected.insert(0, "`void`".to_owned());
+                err
+            })?)
+        };
+        let name = self.identifier()?;
+
+        self.expect_symbol(&'(')?;
+        let parameters = self.parse_parameter_list()?;
+        let alternatives: &[&str] = if parameters.is_empty() {
+            &["type"]
+        } else {
+            &["`,`"]
+        };
+        self.expect_symbol_or(&')', alternatives)?;
+
+        self.expect_symbol(&'{')?;
+        let mut vars = vec![];
+        while self.peek_token()? == Some(Token::Keyword(Keyword::Var)) {
+            match self.parse_var_declaration() {
+                Ok(var) => vars.push(var),
+                Err(err) => {
+                    self.report(err);
+                    self.skip_statement();
+                }
+            }
+        }
+        let statements = self.parse_statements();
+        self.expect_symbol_or(&'}', &["statement"])?;
+
+        Ok(SubroutineDec {
+            kind,
+            return_type,
+            name,
+            parameters,
+            vars,
+            statements,
+            span,
+        })
+    }
+
+    fn parse_var_declaration(&mut self) -> Result<VarDec> {
+        self.tokenizer.advance();
+        let span = self.span();
+        let type_name = self.parse_type()?;
+
+        Ok(VarDec {
+            type_name,
+            names: self.parse_names()?,
+            span,
+        })
+    }
+
+    fn parse_parameter_list(&mut self) -> Result<Vec<Parameter>> {
+        let mut parameters = vec![];
+        if !matches!(
+            self.peek_token()?,
+            Some(
+                Token::Keyword(Keyword::Int | Keyword::Char | Keyword::Boolean)
+                    | Token::Identifier(_)
+            )
+        ) {
+            return Ok(parameters);
+        }
+
+        loop {
+            let type_name = self.parse_type()?;
+            let span = self.span();
+            parameters.push(Parameter {
+                type_name,
+                name: self.identifier()?,
+                span,
+            });
+
+            if !self.next_if_symbol(',')? {
+                return Ok(parameters);
+            }
+        }
+    }
+
+    /// Parses statements up to closing `}`, recovering from errors in them
+    fn parse_statements(&mut self) -> Vec<Statement> {
+        let mut statements = vec![];
+
+        loop {
+            let statement = match self.peek_token() {
+                Ok(None | Some(Token::Symbol(&'}'))) => return statements,
+                Ok(Some(token)) if is_statement_start(&token) => self.parse_statement(),
+                _ => self.next_matching(&["statement", "`}`"], Err),
+            };
+
+            match statement {
+                Ok(statement) => statements.push(statement),
+                Err(err) => {
+                    self.report(err);
+                    self.skip_statement();
+                }
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement> {
+        let token = self.peek_token()?;
+        let span = self.span();
+
+        let kind = match token {
+            Some(Token::Keyword(Keyword::Let)) => StatementKind::Let(self.parse_let_statement()?),
+            Some(Token::Keyword(Keyword::If)) => StatementKind::If(self.parse_if_statement()?),
+            Some(Token::Keyword(Keyword::While)) => {
+                StatementKind::While(self.parse_while_statement()?)
+            }
+            Some(Token::Keyword(Keyword::Do)) => {
+                self.tokenizer.advance();
+                let name = self.identifier()?;
+                let call = self.parse_subroutine_call(name, span)?;
+                self.expect_symbol(&';')?;
+                StatementKind::Do(call)
+            }
+            Some(Token::Keyword(Keyword::Return)) => {
+                self.tokenizer.advance();
+                let value = if self.peek_token()? == Some(Token::Symbol(&';')) {
+                    None
+                } else {
+                    Some(self.parse_expression()?)
+                };
+                self.expect_symbol_or(&';', &["operator"])?;
+                StatementKind::Return(value)
+            }
+            token => return Err(self.unexpected(token.as_ref(), &["statement"])),
+        };
+
+        Ok(Statement { kind, span })
+    }
+
+    /// `{ statements }`
+    fn parse_block(&mut self) -> Result<Vec<Statement>> {
+        self.expect_symbol(&'{')?;
+        let statements = self.parse_statements();
+        self.expect_symbol_or(&'}', &["statement"])?;
+
+        Ok(statements)
+    }
+
+    /// `( expression )`
+    fn parse_condition(&mut self) -> Result<Expression> {
+        self.expect_symbol(&'(')?;
+        let condition = self.parse_expression()?;
+        self.expect_symbol_or(&')', &["operator"])?;
+
+        Ok(condition)
+    }
+
+    fn parse_let_statement(&mut self) -> Result<LetStatement> {
+        let token = self.next_token(&["`let`"])?;
+        self.expect(&token, Token::Keyword(Keyword::Let))?;
+
+        let name = self.identifier()?;
+        let index = if self.next_if_symbol('[')? {
+            let index = self.parse_expression()?;
+            self.expect_symbol_or(&']', &["operator"])?;
+            Some(index)
+        } else {
+            None
+        };
+
+        let alternatives: &[&str] = if index.is_none() { &["`[`"] } else { &[] };
+        self.expect_symbol_or(&'=', alternatives)?;
+        let value = self.parse_expression()?;
+        self.expect_symbol_or(&';', &["operator"])?;
+
+        Ok(LetStatement { name, index, value })
+    }
+
+    fn parse_if_statement(&mut self) -> Result<IfStatement> {
+        let token = self.next_token(&["`if`"])?;
+        self.expect(&token, Token::Keyword(Keyword::If))?;
+
+        let condition = self.parse_condition()?;
+        let statements = self.parse_block()?;
+
+        let otherwise = if self.peek_token()? == Some(Token::Keyword(Keyword::Else)) {
+            self.tokenizer.advance();
+            Some(self.parse_block()?)
+        } else {
+            None
+        };
+
+        Ok(IfStatement {
+            condition,
+            statements,
+            otherwise,
+        })
+    }
+
+    fn parse_while_statement(&mut self) -> Result<WhileStatement> {
+        let token = self.next_token(&["`while`"])?;
+        self.expect(&token, Token::Keyword(Keyword::While))?;
+
+        Ok(WhileStatement {
+            condition: self.parse_condition()?,
+            statements: self.parse_block()?,
+        })
+    }
+
+    /// Rest of subroutine call after its first identifier
+    fn parse_subroutine_call(&mut self, name: String, span: Span) -> Result<SubroutineCall> {
+        let (receiver, name) = if self.next_if_symbol('.')? {
+            (Some(name), self.identifier()?)
+        } else {
+            (None, name)
+        };
+
+        let alternatives: &[&str] = if receiver.is_none() { &["`.`"] } else { &[] };
+        self.expect_symbol_or(&'(', alternatives)?;
+        let arguments = self.parse_expression_list()?;
+        let alternatives: &[&str] = if arguments.is_empty() {
+            &["expression"]
+        } else {
+            &["`,`", "operator"]
+        };
+        self.expect_symbol_or(&')', alternatives)?;
+
+        Ok(SubroutineCall {
+            receiver,
+            name,
+            arguments,
+            span,
+        })
+    }
+
+    fn parse_expression_list(&mut self) -> Result<Vec<Expression>> {
+        let mut expressions = vec![];
+        if self.peek_token()? == Some(Token::Symbol(&')')) {
+            return Ok(expressions);
+        }
+
+        loop {
+            expressions.push(self.parse_expression()?);
+            if !self.next_if_symbol(',')? {
+                return Ok(expressions);
+            }
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression> {
+        let term = self.parse_term()?;
+        let span = term.span;
+
+        let mut operations = vec![];
+        while let Some(Token::Symbol(symbol)) = self.peek_token()? {
+            let Some(op) = BinaryOp::from_symbol(*symbol) else {
+                break;
+            };
+            self.tokenizer.advance();
+            operations.push((op, self.parse_term()?));
+        }
+
+        Ok(Expression {
+            term,
+            operations,
+            span,
+        })
+    }
+
+    fn parse_term(&mut self) -> Result<Term> {
+        let token = self.next_matching(&["expression"], |token| match token {
+            Token::IntConst(_)
+            | Token::StringConst(_)
+            | Token::Keyword(Keyword::True | Keyword::False | Keyword::Null | Keyword::This)
+            | Token::Symbol(&'(' | &'-' | &'~')
+            | Token::Identifier(_) => Ok(token),
+            token => Err(token),
+        })?;
+        let span = self.span();
+
+        let kind = match token {
+            Token::IntConst(value) => TermKind::IntConst(value),
+            Token::StringConst(string) => TermKind::StringConst(string),
+            Token::Keyword(Keyword::True) => TermKind::Keyword(KeywordConst::True),
+            Token::Keyword(Keyword::False) => TermKind::Keyword(KeywordConst::False),
+            Token::Keyword(Keyword::Null) => TermKind::Keyword(KeywordConst::Null),
+            Token::Keyword(Keyword::This) => TermKind::Keyword(KeywordConst::This),
+            Token::Symbol(&'(') => {
+                let expression = self.parse_expression()?;
+                self.expect_symbol_or(&')', &["operator"])?;
+                TermKind::Parenthesized(Box::new(expression))
+            }
+            Token::Symbol(&'-') => TermKind::Unary(UnaryOp::Neg, Box::new(self.parse_term()?)),
+            Token::Symbol(&'~') => TermKind::Unary(UnaryOp::Not, Box::new(self.parse_term()?)),
+            Token::Identifier(name) => match self.peek_token()? {
+                Some(Token::Symbol(&'[')) => {
+                    self.tokenizer.advance();
+                    let index = self.parse_expression()?;
+                    self.expect_symbol_or(&']', &["operator"])?;
+                    TermKind::Index(name, Box::new(index))
+                }
+                Some(Token::Symbol(&'(') | Token::Symbol(&'.')) => {
+                    TermKind::Call(self.parse_subroutine_call(name, span)?)
+                }
+                _ => TermKind::Var(name),
+            },
+            token => unreachable!("{token:?} is not accepted as term"),
+        };
+
+        Ok(Term { kind, span })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Class {
+        CompilationEngine::new(source.as_bytes()).parse().unwrap()
+    }
+
+    #[test]
+    fn test_expect() {
+        let c = CompilationEngine::new("class Main {}".as_bytes());
+
+        c.expect(&Token::Keyword(Keyword::If), Token::Keyword(Keyword::If))
+            .unwrap();
+        c.expect(&Token::IntConst(1), Token::IntConst(1)).unwrap();
+        c.expect(&Token::Symbol(&'"'), Token::Symbol(&'"')).unwrap();
+    }
+
+    #[test]
+    fn test_expect_reports_mismatch() {
+        let c = CompilationEngine::new("class Main {}".as_bytes());
+
+        let err = c
+            .expect(&Token::Keyword(Keyword::If), Token::Keyword(Keyword::Do))
+            .unwrap_err();
+        assert_eq!(err.expected, vec!["`do`"]);
+        assert_eq!(err.found, "`if`");
+    }
+
+    /// Parses source expecting syntax errors
+    fn syntax_errors(source: &str) -> Vec<SyntaxError> {
+        CompilationEngine::new(source.as_bytes())
+            .parse()
+            .unwrap_err()
+            .0
+    }
+
+    fn syntax_error(source: &str) -> SyntaxError {
+        syntax_errors(source).remove(0)
+    }
+
+    #[test]
+    fn reports_span_and_expected_set() {
+        let err = syntax_error(
+            "class Main {
+  function void f() {
+    let x = 1 }
+}",
+        );
+        assert_eq!(
+            err.span,
+            Span {
+                line: 3,
+                column: 15
+            }
+        );
+        assert_eq!(err.expected, vec!["operator", "`;`"]);
+        assert_eq!(err.found, "`}`");
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 15: expected operator or `;`, found `}`\n  |\n3 |     let x = 1 }\n  |               ^"
+        );
+
+        let err = syntax_error(
+            "class Main {
+  field int x
+  method void f() { return; }
+}",
+        );
+        assert_eq!(err.span, Span { line: 3, column: 3 });
+        assert_eq!(err.expected, vec!["`,`", "`;`"]);
+        assert_eq!(err.found, "`method`");
+
+        let err = syntax_error(
+            "class Main {
+  function void f() {
+    while (x) { do f() }",
+        );
+        assert_eq!(
+            err.span,
+            Span {
+                line: 3,
+                column: 24
+            }
+        );
+        assert_eq!(err.found, "`}`");
+
+        let err = syntax_error("class Main { function void f() { return; }");
+        assert_eq!(err.expected, vec!["subroutine declaration", "`}`"]);
+        assert_eq!(err.found, "end of file");
+        assert_eq!(
+            err.span,
+            Span {
+                line: 1,
+                column: 43
+            }
+        );
+
+        let err = syntax_error("class Main { method () { return; } }");
+        assert_eq!(err.expected, vec!["`void`", "type"]);
+        assert_eq!(err.found, "`(`");
+
+        let err = syntax_error("class Main { function void f() { let a = b + ; } }");
+        assert_eq!(err.expected, vec!["expression"]);
+        assert_eq!(err.found, "`;`");
+
+        let err = syntax_error("Main {}");
+        assert_eq!(err.expected, vec!["`class`"]);
+        assert_eq!(err.span, Span { line: 1, column: 1 });
+    }
+
+    #[test]
+    fn recovers_to_report_every_error() {
+        let errors = syntax_errors(
+            "class Main {
+  field int x
+  static boolean y;
+
+  method void f(int a,) {
+    let x = 1;
+  }
+
+  function void g() {
+    var int a b;
+    let a = ;
+    if (a + ) { let a = 1; } else { do h(; }
+    let a = a + 1
+    while (~a) { do Output.printInt(a) let a = a - 1; }
+    var int c;
+    a = 2;
+    return;
+  }
+
+  static int late;
+
+  function int h() {
+    return 1 $ 2;
+  }
+}
+garbage tokens here",
+        );
+        let found: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|err| (err.span.line, err.found.as_str()))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (3, "`static`"),
+                (5, "`)`"),
+                (10, "`b`"),
+                (11, "`;`"),
+                (12, "`)`"),
+                (14, "`while`"),
+                (14, "`let`"),
+                (15, "`var`"),
+                (16, "`a`"),
+                (20, "`static`"),
+                (23, "symbol `$`"),
+                (26, "`garbage`"),
+            ]
+        );
+        assert_eq!(errors[9].expected, vec!["subroutine declaration", "`}`"]);
+        assert_eq!(
+            errors[11].to_string().lines().next().unwrap(),
+            "line 26, column 1: expected end of file, found `garbage`"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_symbol() {
+        let err = syntax_error(
+            "class Main {
+	field int #x;
+}",
+        );
+
+        assert_eq!(
+            err.span,
+            Span {
+                line: 2,
+                column: 12
+            }
+        );
+        assert!(err.expected.is_empty());
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 12: unexpected symbol `#`\n  |\n2 | \tfield int #x;\n  | \t          ^"
+        );
+    }
+
+    #[test]
+    fn parses_declarations() {
+        let class = parse(
+            "class Point {\n  field int x, y;\n  static Point origin;\n\n  method void move(int dx, Point to) {\n    var Array a;\n    return;\n  }\n}",
+        );
+
+        assert_eq!(class.name, "Point");
+        assert_eq!(class.vars[0].names, vec!["x", "y"]);
+        assert_eq!(class.vars[1].kind, ClassVarKind::Static);
+        assert_eq!(class.vars[1].type_name, Type::Class("Point".to_owned()));
+        assert_eq!(class.vars[1].span.line, 3);
+
+        let method = &class.subroutines[0];
+        assert_eq!(method.kind, SubroutineKind::Method);
+        assert_eq!(method.return_type, None);
+        assert_eq!(method.parameters.len(), 2);
+        assert_eq!(method.parameters[1].type_name.to_string(), "Point");
+        assert_eq!(method.vars[0].names, vec!["a"]);
+        assert_eq!(method.span.line, 5);
+        assert_eq!(method.statements[0].span.line, 7);
+        assert_eq!(method.statements[0].kind, StatementKind::Return(None));
+    }
+
+    #[test]
+    fn parses_terms_and_calls() {
+        let class = parse(
+            "class Main { function void main() {\n  do a.b(x[1], -~y);\n  let s = Main.f(\"s\", (1 + 2) * 3);\n  return;\n} }",
+        );
+        let statements = &class.subroutines[0].statements;
+
+        let StatementKind::Do(call) = &statements[0].kind else {
+            panic!("expected do statement");
+        };
+        assert_eq!(call.receiver.as_deref(), Some("a"));
+        assert_eq!(call.name, "b");
+        assert_eq!(call.span.line, 2);
+        assert!(matches!(&call.arguments[0].term.kind, TermKind::Index(name, _) if name == "x"));
+        assert!(matches!(
+            &call.arguments[1].term.kind,
+            TermKind::Unary(UnaryOp::Neg, term) if matches!(term.kind, TermKind::Unary(UnaryOp::Not, _))
+        ));
+
+        let StatementKind::Let(statement) = &statements[1].kind else {
+            panic!("expected let statement");
+        };
+        let TermKind::Call(call) = &statement.value.term.kind else {
+            panic!("expected call");
+        };
+        assert_eq!(call.arguments.len(), 2);
+        assert_eq!(call.arguments[1].operations[0].0, BinaryOp::Mul);
+        assert!(matches!(
+            call.arguments[1].term.kind,
+            TermKind::Parenthesized(_)
+        ));
+    }
+
+    /// Parses expression returned by a function
+    fn expression(source: &str) -> Expression {
+        let class = parse(&format!(
+            "class Main {{ function int f() {{ return {source}; }} }}"
+        ));
+
+        match &class.subroutines[0].statements[0].kind {
+            StatementKind::Return(Some(expression)) => expression.clone(),
+            kind => panic!("expected return statement, found {kind:?}"),
+        }
+    }
+
+    /// Writes expression with every binary operation in parentheses
+    fn render(expression: &Expression) -> String {
+        fn render_term(term: &Term) -> String {
+            match &term.kind {
+                TermKind::IntConst(value) => value.to_string(),
+                TermKind::StringConst(string) => format!("\"{string}\""),
+                TermKind::Keyword(keyword) => format!("{keyword:?}").to_lowercase(),
+                TermKind::Var(name) => name.clone(),
+                TermKind::Index(name, index) => format!("{name}[{}]", render(index)),
+                TermKind::Call(call) => {
+                    let arguments: Vec<String> = call.arguments.iter().map(render).collect();
+                    match &call.receiver {
+                        Some(receiver) => {
+                            format!("{receiver}.{}({})", call.name, arguments.join(", "))
+                        }
+                        None => format!("{}({})", call.name, arguments.join(", ")),
+                    }
+                }
+                TermKind::Parenthesized(expression) => render(expression),
+                TermKind::Unary(op, operand) => format!("{}{}", op.symbol(), render_term(operand)),
+            }
+        }
+
+        expression
+            .operations
+            .iter()
+            .fold(render_term(&expression.term), |left, (op, right)| {
+                format!("({left} {} {})", op.symbol(), render_term(right))
+            })
+    }
+
+    #[test]
+    fn parses_expression_corpus() {
+        let corpus = [
+            ("a + b + c", "((a + b) + c)"),
+            ("x * 2 - y / 3", "(((x * 2) - y) / 3)"),
+            ("1 + (2 * 3)", "(1 + (2 * 3))"),
+            ("a & b | c = d", "(((a & b) | c) = d)"),
+            ("a < b > c", "((a < b) > c)"),
+            ("--x", "--x"),
+            ("~~(a < b)", "~~(a < b)"),
+            ("-x - -y", "(-x - -y)"),
+            ("-(x + 1) * ~y", "(-(x + 1) * ~y)"),
+            ("((a))", "a"),
+            ("f(a + b, g(c) * 2)", "f((a + b), (g(c) * 2))"),
+            (
+                "(Math.max(a, b)) + arr[i + 1]",
+                "(Math.max(a, b) + arr[(i + 1)])",
+            ),
+            ("arr[arr[0] - 1] + p.x()", "(arr[(arr[0] - 1)] + p.x())"),
+            ("\"s\" = null | true", "((\"s\" = null) | true)"),
+            (
+                "(this = that) & (1 + 2 + 3 > 5)",
+                "((this = that) & (((1 + 2) + 3) > 5))",
+            ),
+        ];
+
+        for (source, expected) in corpus {
+            assert_eq!(render(&expression(source)), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn expression_ends_before_closing_symbols() {
+        let class = parse(
+            "class Main { function void f() { let a[i + 1 - j] = g(x, y + z) + 1; do h((a + b) * c); return; } }",
+        );
+        let statements = &class.subroutines[0].statements;
+
+        let StatementKind::Let(statement) = &statements[0].kind else {
+            panic!("expected let statement");
+        };
+        assert_eq!(render(statement.index.as_ref().unwrap()), "((i + 1) - j)");
+        assert_eq!(render(&statement.value), "(g(x, (y + z)) + 1)");
+
+        let StatementKind::Do(call) = &statements[1].kind else {
+            panic!("expected do statement");
+        };
+        assert_eq!(render(&call.arguments[0]), "((a + b) * c)");
+    }
+}
@@ -0,0 +1,478 @@
+use std::io::{self, Result, Write};
+
+use translator::vm::{ArtithmeticOperation, Segment, VmCommand};
+
+use crate::{
+    ast::*,
+    symbol_table::{Kind, Symbol, SymbolTable},
+};
+
+use super::writer::CompilationWriter;
+
+/// Backend generating VM code of a class
+pub struct VmWriter<'a, W: Write> {
+    writer: &'a mut W,
+    symbols: SymbolTable,
+    class_name: String,
+    /// Full name of compiled subroutine, e.g. `Main.main`
+    subroutine: String,
+    /// Labels are numbered from 0 in every subroutine
+    labels: usize,
+}
+
+impl<'a, W: Write> VmWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> VmWriter<'a, W> {
+        VmWriter {
+            writer,
+            symbols: SymbolTable::new(),
+            class_name: String::new(),
+            subroutine: String::new(),
+            labels: 0,
+        }
+    }
+
+    /// Adds every name with given type and kind to symbol table
+    fn define(&mut self, names: &[String], type_name: &Type, kind: Kind) -> Result<()> {
+        for name in names {
+            if !self.symbols.define(name, &type_name.to_string(), kind) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: `{name}` is already defined", self.class_name),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn variable(&self, name: &str) -> Result<Symbol> {
+        self.symbols.get(name).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: undefined variable `{name}`", self.subroutine),
+            )
+        })
+    }
+
+    fn command(&mut self, command: VmCommand) -> Result<()> {
+        writeln!(self.writer, "{command}")
+    }
+
+    fn arithmetic(&mut self, op: ArtithmeticOperation) -> Result<()> {
+        self.command(VmCommand::arithmetic(op))
+    }
+
+    fn push_variable(&mut self, symbol: &Symbol) -> Result<()> {
+        self.command(VmCommand::push(symbol.kind.segment(), symbol.index))
+    }
+
+    fn next_label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Pushes address of `name[index]`
+    fn write_address(&mut self, name: &str, index: &Expression) -> Result<()> {
+        let array = self.variable(name)?;
+
+        self.push_variable(&array)?;
+        self.write_expression(index)?;
+        self.arithmetic(ArtithmeticOperation::Add)
+    }
+}
+
+impl<'a, W: Write> CompilationWriter for VmWriter<'a, W> {
+    fn write_class(&mut self, class: &Class) -> Result<()> {
+        self.class_name = class.name.clone();
+
+        for declaration in &class.vars {
+            self.write_class_var_declaration(declaration)?;
+        }
+        for subroutine in &class.subroutines {
+            self.write_subroutine_declaration(subroutine)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_class_var_declaration(&mut self, declaration: &ClassVarDec) -> Result<()> {
+        let kind = match declaration.kind {
+            ClassVarKind::Static => Kind::Static,
+            ClassVarKind::Field => Kind::Field,
+        };
+
+        self.define(&declaration.names, &declaration.type_name, kind)
+    }
+
+    fn write_subroutine_declaration(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        self.subroutine = format!("{}.{}", self.class_name, subroutine.name);
+
+        self.symbols.start_subroutine();
+        self.labels = 0;
+        if subroutine.kind == SubroutineKind::Method {
+            let class_name = self.class_name.clone();
+            self.symbols.define("this", &class_name, Kind::Argument);
+        }
+
+        self.write_parameter_list(&subroutine.parameters)?;
+        self.write_subroutine_body(subroutine)
+    }
+
+    fn write_parameter_list(&mut self, parameters: &[Parameter]) -> Result<()> {
+        for parameter in parameters {
+            if !self.symbols.define(
+                &parameter.name,
+                &parameter.type_name.to_string(),
+                Kind::Argument,
+            ) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: `{}` is already defined",
+                        self.subroutine, parameter.name
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_subroutine_body(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        for declaration in &subroutine.vars {
+            self.write_var_declaration(declaration)?;
+        }
+
+        let locals = self.symbols.var_count(Kind::Local);
+        self.command(VmCommand::function(self.subroutine.clone(), locals))?;
+
+        match subroutine.kind {
+            SubroutineKind::Constructor => {
+                let fields = self.symbols.var_count(Kind::Field);
+                self.command(VmCommand::push(Segment::Constant, fields))?;
+                self.command(VmCommand::call("Memory.alloc", 1))?;
+                self.command(VmCommand::pop(Segment::Pointer, 0))?;
+            }
+            SubroutineKind::Method => {
+                self.command(VmCommand::push(Segment::Argument, 0))?;
+                self.command(VmCommand::pop(Segment::Pointer, 0))?;
+            }
+            SubroutineKind::Function => {}
+        }
+
+        self.write_statements(&subroutine.statements)
+    }
+
+    fn write_var_declaration(&mut self, declaration: &VarDec) -> Result<()> {
+        self.define(&declaration.names, &declaration.type_name, Kind::Local)
+    }
+
+    fn write_statements(&mut self, statements: &[Statement]) -> Result<()> {
+        statements
+            .iter()
+            .try_for_each(|statement| self.write_statement(statement))
+    }
+
+    fn write_let_statement(&mut self, statement: &LetStatement) -> Result<()> {
+        let target = self.variable(&statement.name)?;
+
+        match &statement.index {
+            Some(index) => {
+                self.write_address(&statement.name, index)?;
+                self.write_expression(&statement.value)?;
+
+                // Value may itself use `that`, so address is set only after it is computed
+                self.command(VmCommand::pop(Segment::Temp, 0))?;
+                self.command(VmCommand::pop(Segment::Pointer, 1))?;
+                self.command(VmCommand::push(Segment::Temp, 0))?;
+                self.command(VmCommand::pop(Segment::That, 0))
+            }
+            None => {
+                self.write_expression(&statement.value)?;
+                self.command(VmCommand::pop(target.kind.segment(), target.index))
+            }
+        }
+    }
+
+    fn write_if_statement(&mut self, statement: &IfStatement) -> Result<()> {
+        let label = self.next_label();
+        let else_label = format!("IF_ELSE{label}");
+        let end_label = format!("IF_END{label}");
+
+        self.write_expression(&statement.condition)?;
+        self.arithmetic(ArtithmeticOperation::Not)?;
+        self.command(VmCommand::if_goto(&else_label))?;
+
+        self.write_statements(&statement.statements)?;
+
+        match &statement.otherwise {
+            Some(otherwise) => {
+                self.command(VmCommand::goto(&end_label))?;
+                self.command(VmCommand::label(else_label))?;
+                self.write_statements(otherwise)?;
+                self.command(VmCommand::label(end_label))
+            }
+            None => self.command(VmCommand::label(else_label)),
+        }
+    }
+
+    fn write_while_statement(&mut self, statement: &WhileStatement) -> Result<()> {
+        let label = self.next_label();
+        let start_label = format!("WHILE_EXP{label}");
+        let end_label = format!("WHILE_END{label}");
+
+        self.command(VmCommand::label(&start_label))?;
+        self.write_expression(&statement.condition)?;
+        self.arithmetic(ArtithmeticOperation::Not)?;
+        self.command(VmCommand::if_goto(&end_label))?;
+
+        self.write_statements(&statement.statements)?;
+
+        self.command(VmCommand::goto(start_label))?;
+        self.command(VmCommand::label(end_label))
+    }
+
+    fn write_do_statement(&mut self, call: &SubroutineCall) -> Result<()> {
+        self.write_subroutine_call(call)?;
+
+        // Returned value is not used
+        self.command(VmCommand::pop(Segment::Temp, 0))
+    }
+
+    fn write_return_statement(&mut self, value: Option<&Expression>) -> Result<()> {
+        match value {
+            Some(value) => self.write_expression(value)?,
+            // `void` subroutines return 0, which caller drops
+            None => self.command(VmCommand::push(Segment::Constant, 0))?,
+        }
+
+        self.command(VmCommand::Return)
+    }
+
+    fn write_expression(&mut self, expression: &Expression) -> Result<()> {
+        self.write_term(&expression.term)?;
+
+        for (op, term) in &expression.operations {
+            self.write_term(term)?;
+            self.command(match op {
+                BinaryOp::Add => VmCommand::arithmetic(ArtithmeticOperation::Add),
+                BinaryOp::Sub => VmCommand::arithmetic(ArtithmeticOperation::Sub),
+                BinaryOp::Mul => VmCommand::call("Math.multiply", 2),
+                BinaryOp::Div => VmCommand::call("Math.divide", 2),
+                BinaryOp::And => VmCommand::arithmetic(ArtithmeticOperation::And),
+                BinaryOp::Or => VmCommand::arithmetic(ArtithmeticOperation::Or),
+                BinaryOp::Lt => VmCommand::arithmetic(ArtithmeticOperation::Lt),
+                BinaryOp::Gt => VmCommand::arithmetic(ArtithmeticOperation::Gt),
+                BinaryOp::Eq => VmCommand::arithmetic(ArtithmeticOperation::Eq),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn write_term(&mut self, term: &Term) -> Result<()> {
+        match &term.kind {
+            TermKind::IntConst(value) => self.command(VmCommand::push(Segment::Constant, *value)),
+            TermKind::StringConst(string) => {
+                let length = string.chars().count() as u16;
+                self.command(VmCommand::push(Segment::Constant, length))?;
+                self.command(VmCommand::call("String.new", 1))?;
+
+                // Tokenizer allows only printable ASCII, whose codes match Hack ones
+                for char in string.chars() {
+                    self.command(VmCommand::push(Segment::Constant, char as u16))?;
+                    self.command(VmCommand::call("String.appendChar", 2))?;
+                }
+
+                Ok(())
+            }
+            TermKind::Keyword(KeywordConst::True) => {
+                self.command(VmCommand::push(Segment::Constant, 0))?;
+                self.arithmetic(ArtithmeticOperation::Not)
+            }
+            TermKind::Keyword(KeywordConst::False) | TermKind::Keyword(KeywordConst::Null) => {
+                self.command(VmCommand::push(Segment::Constant, 0))
+            }
+            TermKind::Keyword(KeywordConst::This) => {
+                self.command(VmCommand::push(Segment::Pointer, 0))
+            }
+            TermKind::Var(name) => {
+                let variable = self.variable(name)?;
+                self.push_variable(&variable)
+            }
+            TermKind::Index(name, index) => {
+                self.write_address(name, index)?;
+                self.command(VmCommand::pop(Segment::Pointer, 1))?;
+                self.command(VmCommand::push(Segment::That, 0))
+            }
+            TermKind::Call(call) => self.write_subroutine_call(call),
+            TermKind::Parenthesized(expression) => self.write_expression(expression),
+            TermKind::Unary(op, term) => {
+                self.write_term(term)?;
+                self.arithmetic(match op {
+                    UnaryOp::Neg => ArtithmeticOperation::Neg,
+                    UnaryOp::Not => ArtithmeticOperation::Not,
+                })
+            }
+        }
+    }
+
+    /// `name(args)` calls method of `this`, `var.name(args)` calls method
+    /// of object in variable, `Class.name(args)` calls function or constructor
+    fn write_subroutine_call(&mut self, call: &SubroutineCall) -> Result<()> {
+        let (function, receiver) = match &call.receiver {
+            Some(name) => match self.symbols.get(name).cloned() {
+                Some(object) => {
+                    self.push_variable(&object)?;
+                    (format!("{}.{}", object.type_name, call.name), 1)
+                }
+                None => (format!("{name}.{}", call.name), 0),
+            },
+            None => {
+                self.command(VmCommand::push(Segment::Pointer, 0))?;
+                (format!("{}.{}", self.class_name, call.name), 1)
+            }
+        };
+
+        self.write_expression_list(&call.arguments)?;
+
+        let arguments = receiver + call.arguments.len() as u16;
+        self.command(VmCommand::call(function, arguments))
+    }
+
+    fn write_expression_list(&mut self, expressions: &[Expression]) -> Result<()> {
+        expressions
+            .iter()
+            .try_for_each(|expression| self.write_expression(expression))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compilation_engine::CompilationEngine;
+
+    fn compile(source: &str) -> String {
+        let class = CompilationEngine::new(source.as_bytes()).parse().unwrap();
+        let mut output = vec![];
+        CompilationEngine::write_vm(&class, &mut output).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn constructor_allocates_fields_and_method_sets_this() {
+        let vm = compile(
+            "class Point {
+                field int x, y;
+                static int count;
+
+                constructor Point new(int ax, int ay) {
+                    let x = ax;
+                    let y = ay;
+                    let count = count + 1;
+                    return this;
+                }
+
+                method int sum() {
+                    return x + y;
+                }
+            }",
+        );
+
+        assert_eq!(
+            vm,
+            "function Point.new 0\npush constant 2\ncall Memory.alloc 1\npop pointer 0\n\
+             push argument 0\npop this 0\npush argument 1\npop this 1\n\
+             push static 0\npush constant 1\nadd\npop static 0\npush pointer 0\nreturn\n\
+             function Point.sum 0\npush argument 0\npop pointer 0\n\
+             push this 0\npush this 1\nadd\nreturn\n"
+        );
+    }
+
+    #[test]
+    fn compiles_calls_arrays_and_strings() {
+        let vm = compile(
+            "class Main {
+                function void main() {
+                    var Array a;
+                    var Point p;
+                    let a[1] = a[0];
+                    do p.move(1, Math.abs(-2));
+                    do Output.printString(\"Hi\");
+                    do run();
+                    return;
+                }
+            }",
+        );
+
+        assert_eq!(
+            vm,
+            "function Main.main 2\n\
+             push local 0\npush constant 1\nadd\npush local 0\npush constant 0\nadd\n\
+             pop pointer 1\npush that 0\npop temp 0\npop pointer 1\npush temp 0\npop that 0\n\
+             push local 1\npush constant 1\npush constant 2\nneg\ncall Math.abs 1\n\
+             call Point.move 3\npop temp 0\n\
+             push constant 2\ncall String.new 1\npush constant 72\ncall String.appendChar 2\n\
+             push constant 105\ncall String.appendChar 2\ncall Output.printString 1\npop temp 0\n\
+             push pointer 0\ncall Main.run 1\npop temp 0\n\
+             push constant 0\nreturn\n"
+        );
+    }
+
+    #[test]
+    fn compiles_control_flow_and_operators() {
+        let vm = compile(
+            "class Main {
+                function int f(int n) {
+                    while (n > 0) {
+                        if (~(n = 1)) { let n = n - 1 * 2; } else { return true; }
+                    }
+                    if (n < 0) { return null; }
+                    return false;
+                }
+            }",
+        );
+
+        assert_eq!(
+            vm,
+            "function Main.f 0\n\
+             label WHILE_EXP0\npush argument 0\npush constant 0\ngt\nnot\nif-goto WHILE_END0\n\
+             push argument 0\npush constant 1\neq\nnot\nnot\nif-goto IF_ELSE1\n\
+             push argument 0\npush constant 1\nsub\npush constant 2\ncall Math.multiply 2\npop argument 0\n\
+             goto IF_END1\nlabel IF_ELSE1\npush constant 0\nnot\nreturn\nlabel IF_END1\n\
+             goto WHILE_EXP0\nlabel WHILE_END0\n\
+             push argument 0\npush constant 0\nlt\nnot\nif-goto IF_ELSE2\npush constant 0\nreturn\n\
+             label IF_ELSE2\npush constant 0\nreturn\n"
+        );
+    }
+
+    #[test]
+    fn compiles_operator_chains_left_to_right() {
+        let vm = compile(
+            "class Main {
+                function int f(int x, int y) {
+                    return x * 2 - y / 3 + --x;
+                }
+            }",
+        );
+
+        assert_eq!(
+            vm,
+            "function Main.f 0\n\
+             push argument 0\npush constant 2\ncall Math.multiply 2\n\
+             push argument 1\nsub\npush constant 3\ncall Math.divide 2\n\
+             push argument 0\nneg\nneg\nadd\nreturn\n"
+        );
+    }
+
+    #[test]
+    fn rejects_undefined_variables() {
+        let class =
+            CompilationEngine::new("class Main { function void f() { let x = 1; } }".as_bytes())
+                .parse()
+                .unwrap();
+        let error = CompilationEngine::write_vm(&class, vec![]).unwrap_err();
+
+        assert_eq!(error.to_string(), "Main.f: undefined variable `x`");
+    }
+}
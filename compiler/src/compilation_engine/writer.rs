@@ -1,700 +1,666 @@
-use std::io::{Read, Result, Write};
+use std::io::{Result, Write};
 
-use crate::tokenizer::{Keyword, Token};
+use crate::{
+    ast::*,
+    symbol_table::{Kind, SymbolTable},
+    tokenizer::{xml_writer::SimpleXmlWriter, Keyword, Token},
+};
 
-use super::CompilationEngine;
+use super::NonTerminals;
 
+/// Output backend walking syntax tree of a class, one method per nonterminal
 pub trait CompilationWriter {
-    fn write_term(&mut self) -> Result<()>;
-    fn write_expression_list(&mut self) -> Result<()>;
-    fn write_expression(&mut self) -> Result<()>;
-    fn write_closing_tag(&mut self, data: &[u8]) -> Result<()>;
-    fn write_open_tag(&mut self, data: &[u8]) -> Result<()>;
-    fn write_return_statement(&mut self) -> Result<()>;
-    fn write_do_statement(&mut self) -> Result<()>;
-    fn expect(&self, token: &Token, expected_token: Token);
-    fn write_while_statement(&mut self) -> Result<()>;
-    fn write_if_statement(&mut self) -> Result<()>;
-    fn write_class(&mut self) -> Result<()>;
-    fn write_terminal(&mut self, token: &Token) -> Result<()>;
-    fn write_class_var_declarations(&mut self) -> Result<()>;
-    fn write_subroutine_declarations(&mut self) -> Result<()>;
-    fn write_parameter_list(&mut self) -> Result<()>;
-    fn write_subroutine_body(&mut self) -> Result<()>;
-    fn write_var_declaration(&mut self) -> Result<()>;
-    fn write_statements(&mut self) -> Result<()>;
-    fn write_let_statement(&mut self) -> Result<()>;
+    fn write_class(&mut self, class: &Class) -> Result<()>;
+    fn write_class_var_declaration(&mut self, declaration: &ClassVarDec) -> Result<()>;
+    fn write_subroutine_declaration(&mut self, subroutine: &SubroutineDec) -> Result<()>;
+    fn write_parameter_list(&mut self, parameters: &[Parameter]) -> Result<()>;
+    fn write_subroutine_body(&mut self, subroutine: &SubroutineDec) -> Result<()>;
+    fn write_var_declaration(&mut self, declaration: &VarDec) -> Result<()>;
+    fn write_statements(&mut self, statements: &[Statement]) -> Result<()>;
+    fn write_statement(&mut self, statement: &Statement) -> Result<()> {
+        match &statement.kind {
+            StatementKind::Let(statement) => self.write_let_statement(statement),
+            StatementKind::If(statement) => self.write_if_statement(statement),
+            StatementKind::While(statement) => self.write_while_statement(statement),
+            StatementKind::Do(call) => self.write_do_statement(call),
+            StatementKind::Return(value) => self.write_return_statement(value.as_ref()),
+        }
+    }
+    fn write_let_statement(&mut self, statement: &LetStatement) -> Result<()>;
+    fn write_if_statement(&mut self, statement: &IfStatement) -> Result<()>;
+    fn write_while_statement(&mut self, statement: &WhileStatement) -> Result<()>;
+    fn write_do_statement(&mut self, call: &SubroutineCall) -> Result<()>;
+    fn write_return_statement(&mut self, value: Option<&Expression>) -> Result<()>;
+    fn write_expression(&mut self, expression: &Expression) -> Result<()>;
+    fn write_term(&mut self, term: &Term) -> Result<()>;
+    fn write_subroutine_call(&mut self, call: &SubroutineCall) -> Result<()>;
+    fn write_expression_list(&mut self, expressions: &[Expression]) -> Result<()>;
 }
 
-pub struct XmlWriter<'a, T: Read, W: Write> {
-    compilation_engine: &'a mut CompilationEngine<T>,
+/// What identifier names, see `XmlWriter::annotate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Class,
+    Subroutine,
+    Variable,
+}
+
+/// Writes parse tree as XML, every nonterminal is a tag around its tokens
+pub struct XmlWriter<'a, W: Write> {
     writer: &'a mut W,
     padding: u8,
     padding_level: u8,
+    symbols: SymbolTable,
+    class_name: String,
+    annotate: bool,
+    compatible: bool,
 }
 
-impl<'a, T: Read, W: Write> XmlWriter<'a, T, W> {
-    pub fn new(
-        compilation_engine: &'a mut CompilationEngine<T>,
-        writer: &'a mut W,
-    ) -> XmlWriter<'a, T, W> {
+impl<'a, W: Write> XmlWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> XmlWriter<'a, W> {
         XmlWriter {
-            compilation_engine,
             writer,
             padding: 0,
             padding_level: 2,
+            symbols: SymbolTable::new(),
+            class_name: String::new(),
+            annotate: false,
+            compatible: false,
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
-        let token = self
-            .compilation_engine
-            .tokenizer
-            .advance()
-            .expect("no input found")
-            .expect("failed to get first token");
-
-        token
-    }
-
-    fn peek_token(&mut self) -> Token {
-        self.compilation_engine
-            .tokenizer
-            .peek_token()
-            .expect("no input found")
-            .expect("failed to peek next token")
+    /// Adds category, kind, type, index and usage attributes to `<identifier>` tags,
+    /// e.g. `<identifier category="variable" kind="field" type="int" index="0" usage="defined">`
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
     }
-}
 
-impl<'a, T: Read, W: Write> CompilationWriter for XmlWriter<'a, T, W> {
-    fn expect(&self, token: &Token, expected_token: Token) {
-        if token != &expected_token {
-            panic!(
-                "unexpected token error: expected {:?}, found {:?}",
-                expected_token, token
-            );
-        }
+    /// Pads text of terminals with spaces as reference files of the course do,
+    /// e.g. `<keyword> class </keyword>`
+    pub fn compatible(mut self, compatible: bool) -> Self {
+        self.compatible = compatible;
+        self
     }
 
-    fn write_open_tag(&mut self, data: &[u8]) -> Result<()> {
+    fn write_open_tag(&mut self, tag: NonTerminals) -> Result<()> {
         for _ in 0..self.padding {
             self.writer.write_all(b" ")?;
         }
-        self.writer.write(data)?;
-        self.writer.write_all(b"\n")?;
+        writeln!(self.writer, "<{}>", tag.name())?;
 
-        self.padding = self.padding + self.padding_level;
+        self.padding += self.padding_level;
 
         Ok(())
     }
 
-    fn write_closing_tag(&mut self, data: &[u8]) -> Result<()> {
-        self.padding = self.padding - self.padding_level;
+    fn write_closing_tag(&mut self, tag: NonTerminals) -> Result<()> {
+        self.padding -= self.padding_level;
 
         for _ in 0..self.padding {
             self.writer.write_all(b" ")?;
         }
-        self.writer.write(data)?;
-        self.writer.write_all(b"\n")?;
-
-        Ok(())
+        writeln!(self.writer, "</{}>", tag.name())
     }
 
-    fn write_class(&mut self) -> Result<()> {
-        let token = self // fn write_terminal(&self);
-            .compilation_engine
-            .tokenizer
-            .advance()
-            .expect("no input found")
-            .expect("failed to get first token");
-
-        let result = match token {
-            Token::Keyword(Keyword::Class) => true,
-            _ => false,
-        };
-        if !result {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "first token was not `class`",
-            ));
+    fn write_terminal(&mut self, token: &Token) -> Result<()> {
+        for _ in 0..self.padding {
+            self.writer.write_all(b" ")?;
         }
 
-        self.write_open_tag(b"<class>")?;
-
-        self.write_terminal(&token)?;
-
-        let token = self.next_token();
-        self.write_terminal(&token)?; // class name
-
-        let token = self.next_token();
-        self.write_terminal(&token)?; // `{` symbol
-
-        let mut token = self.peek_token();
-        loop {
-            let has_var_declarations = match token {
-                Token::Keyword(Keyword::Static) | Token::Keyword(Keyword::Field) => true,
-                _ => false,
-            };
+        token.write_xml_with(self.writer, self.compatible)?;
+        self.writer.write_all(b"\n")
+    }
 
-            if has_var_declarations {
-                self.write_class_var_declarations()?;
-                token = self.peek_token();
-            } else {
-                break;
-            }
-        }
+    fn write_keyword(&mut self, keyword: Keyword) -> Result<()> {
+        self.write_terminal(&Token::Keyword(keyword))
+    }
 
-        loop {
-            let has_subroutine_declarations = match token {
-                Token::Keyword(Keyword::Constructor)
-                | Token::Keyword(Keyword::Function)
-                | Token::Keyword(Keyword::Method) => true,
-                _ => false,
-            };
+    fn write_symbol(&mut self, symbol: &'static char) -> Result<()> {
+        self.write_terminal(&Token::Symbol(symbol))
+    }
 
-            if has_subroutine_declarations {
-                self.write_subroutine_declarations()?;
-                token = self.peek_token();
-            } else {
-                break;
-            }
+    fn write_identifier(&mut self, name: &str, category: Category, defined: bool) -> Result<()> {
+        if !self.annotate {
+            return self.write_terminal(&Token::Identifier(name.to_owned()));
         }
 
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&'}'));
-        self.write_terminal(&token)?;
-
-        self.write_closing_tag(b"</class>")?;
-
-        Ok(())
-    }
+        let attributes = match category {
+            Category::Class => "category=\"class\"".to_owned(),
+            Category::Subroutine => "category=\"subroutine\"".to_owned(),
+            Category::Variable => match self.symbols.get(name) {
+                Some(symbol) => format!(
+                    "category=\"variable\" kind=\"{}\" type=\"{}\" index=\"{}\"",
+                    symbol.kind, symbol.type_name, symbol.index
+                ),
+                None => "category=\"undefined\"".to_owned(),
+            },
+        };
+        let usage = if defined { "defined" } else { "used" };
+        let padding = if self.compatible { " " } else { "" };
 
-    fn write_terminal(&mut self, token: &Token) -> Result<()> {
         for _ in 0..self.padding {
             self.writer.write_all(b" ")?;
         }
+        writeln!(
+            self.writer,
+            "<identifier {attributes} usage=\"{usage}\">{padding}{name}{padding}</identifier>"
+        )
+    }
 
-        match token {
-            Token::Keyword(keyword) => {
-                let tag = match keyword {
-                    Keyword::Class => "class",
-                    Keyword::Method => "method",
-                    Keyword::Function => "function",
-                    Keyword::Constructor => "constructor",
-                    Keyword::Int => "int",
-                    Keyword::Boolean => "boolean",
-                    Keyword::Char => "char",
-                    Keyword::Void => "void",
-                    Keyword::Var => "var",
-                    Keyword::Static => "static",
-                    Keyword::Field => "field",
-                    Keyword::Let => "let",
-                    Keyword::Do => "do",
-                    Keyword::If => "if",
-                    Keyword::Else => "else",
-                    Keyword::While => "while",
-                    Keyword::Return => "return",
-                    Keyword::True => "true",
-                    Keyword::False => "false",
-                    Keyword::Null => "null",
-                    Keyword::This => "this",
-                };
-                self.writer.write_all(b"<keyword>")?;
-                self.writer.write_all(tag.as_bytes())?;
-                self.writer.write_all(b"</keyword>\n")?;
-            }
-            Token::Symbol(symbol) => {
-                let mut tmp = [0u8; 4];
-                let result = match symbol {
-                    '<' => "&lt;",
-                    '>' => "&gt;",
-                    '&' => "&amp;",
-                    '"' => "&quot",
-                    _ => symbol.encode_utf8(&mut tmp),
-                };
-                self.writer.write_all(b"<symbol>")?;
-                self.writer.write_all(result.as_bytes())?;
-                self.writer.write_all(b"</symbol>\n")?;
-            }
-            Token::Identifier(ident) => {
-                self.writer.write_all(b"<identifier>")?;
-                self.writer.write_all(ident.as_bytes())?;
-                self.writer.write_all(b"</identifier>\n")?;
-            }
-            Token::IntConst(int) => {
-                let int = int.to_string(); // FIXME: need to avoid allocation
-                self.writer.write_all(b"<integerConstant>")?;
-                self.writer.write_all(int.as_bytes())?;
-                self.writer.write_all(b"</integerConstant>\n")?;
-            }
-            Token::StringConst(string) => {
-                self.writer.write_all(b"<stringConstant>")?;
-                self.writer.write_all(string.as_bytes())?;
-                self.writer.write_all(b"</stringConstant>\n")?;
-            }
+    /// Writes `int`, `char`, `boolean` or class name
+    fn write_type(&mut self, type_name: &Type) -> Result<()> {
+        match type_name {
+            Type::Int => self.write_keyword(Keyword::Int),
+            Type::Char => self.write_keyword(Keyword::Char),
+            Type::Boolean => self.write_keyword(Keyword::Boolean),
+            Type::Class(name) => self.write_identifier(name, Category::Class, false),
         }
-
-        Ok(())
     }
 
-    fn write_class_var_declarations(&mut self) -> Result<()> {
-        self.write_open_tag(b"<classVarDec>")?;
-        while let Some(Ok(token)) = self.compilation_engine.tokenizer.advance() {
-            let is_end = match token {
-                Token::Symbol(';') => true,
-                _ => false,
-            };
-
-            self.write_terminal(&token)?;
+    /// Writes `type name (, name)* ;`, defining every name
+    fn write_names(&mut self, type_name: &Type, names: &[String], kind: Kind) -> Result<()> {
+        self.write_type(type_name)?;
 
-            if is_end {
-                break;
+        for (index, name) in names.iter().enumerate() {
+            if index > 0 {
+                self.write_symbol(&',')?;
             }
+            self.symbols.define(name, &type_name.to_string(), kind);
+            self.write_identifier(name, Category::Variable, true)?;
         }
 
-        self.write_closing_tag(b"</classVarDec>")?;
-        Ok(())
+        self.write_symbol(&';')
     }
 
-    fn write_subroutine_declarations(&mut self) -> Result<()> {
-        self.write_open_tag(b"<subroutineDec>")?;
-
-        for _ in 0..4 {
-            let token = self.next_token();
-            self.write_terminal(&token)?;
-        }
-
-        self.write_parameter_list()?;
-
-        let token = &self.next_token();
-        self.write_terminal(token)?; // close bracket
-
-        self.write_subroutine_body()?;
-
-        self.write_closing_tag(b"</subroutineDec>")?;
-
-        Ok(())
+    fn write_block(&mut self, statements: &[Statement]) -> Result<()> {
+        self.write_symbol(&'{')?;
+        self.write_statements(statements)?;
+        self.write_symbol(&'}')
     }
 
-    fn write_parameter_list(&mut self) -> Result<()> {
-        self.write_open_tag(b"<parameterList>")?;
-
-        loop {
-            let token = self.peek_token();
-            if matches!(token, Token::Symbol(')')) {
-                break;
-            }
-
-            self.next_token();
-            self.write_terminal(&token)?;
-        }
-
-        self.write_closing_tag(b"</parameterList>")?;
-
-        Ok(())
+    fn write_condition(&mut self, condition: &Expression) -> Result<()> {
+        self.write_symbol(&'(')?;
+        self.write_expression(condition)?;
+        self.write_symbol(&')')
     }
+}
 
-    fn write_subroutine_body(&mut self) -> Result<()> {
-        self.write_open_tag(b"<subroutineBody>")?;
+impl<'a, W: Write> CompilationWriter for XmlWriter<'a, W> {
+    fn write_class(&mut self, class: &Class) -> Result<()> {
+        self.write_open_tag(NonTerminals::Class)?;
 
-        let mut token = self.next_token();
-        self.write_terminal(&token)?; // {
+        self.write_keyword(Keyword::Class)?;
+        self.write_identifier(&class.name, Category::Class, true)?;
+        self.class_name = class.name.clone();
+        self.write_symbol(&'{')?;
 
-        token = self.peek_token();
-        loop {
-            if matches!(token, Token::Keyword(Keyword::Var)) {
-                let t = self.next_token();
-                self.write_open_tag(b"<varDec>")?;
-                self.write_terminal(&t)?;
-                self.write_var_declaration()?;
-                self.write_closing_tag(b"</varDec>")?;
-                token = self.peek_token();
-            } else {
-                break;
-            }
+        for declaration in &class.vars {
+            self.write_class_var_declaration(declaration)?;
+        }
+        for subroutine in &class.subroutines {
+            self.write_subroutine_declaration(subroutine)?;
         }
 
-        self.write_statements()?;
-
-        token = self.next_token();
-        self.expect(&token, Token::Symbol(&'}'));
-        self.write_terminal(&token)?;
+        self.write_symbol(&'}')?;
 
-        self.write_closing_tag(b"</subroutineBody>")?;
-        Ok(())
+        self.write_closing_tag(NonTerminals::Class)
     }
 
-    fn write_var_declaration(&mut self) -> Result<()> {
-        loop {
-            let token = self.next_token();
-            self.write_terminal(&token)?;
+    fn write_class_var_declaration(&mut self, declaration: &ClassVarDec) -> Result<()> {
+        self.write_open_tag(NonTerminals::ClassVarDec)?;
 
-            if matches!(token, Token::Symbol(';')) {
-                break;
+        let kind = match declaration.kind {
+            ClassVarKind::Static => {
+                self.write_keyword(Keyword::Static)?;
+                Kind::Static
             }
-        }
-        Ok(())
+            ClassVarKind::Field => {
+                self.write_keyword(Keyword::Field)?;
+                Kind::Field
+            }
+        };
+        self.write_names(&declaration.type_name, &declaration.names, kind)?;
+
+        self.write_closing_tag(NonTerminals::ClassVarDec)
     }
 
-    fn write_statements(&mut self) -> Result<()> {
-        self.write_open_tag(b"<statements>")?;
+    fn write_subroutine_declaration(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        self.write_open_tag(NonTerminals::SubroutineDec)?;
 
-        let token = self.peek_token();
+        self.write_keyword(match subroutine.kind {
+            SubroutineKind::Constructor => Keyword::Constructor,
+            SubroutineKind::Function => Keyword::Function,
+            SubroutineKind::Method => Keyword::Method,
+        })?;
 
-        if !matches!(
-            token,
-            Token::Keyword(Keyword::Let)
-                | Token::Keyword(Keyword::If)
-                | Token::Keyword(Keyword::While)
-                | Token::Keyword(Keyword::Do)
-                | Token::Keyword(Keyword::Return)
-        ) {
-            self.write_closing_tag(b"</statements>")?;
+        self.symbols.start_subroutine();
+        if subroutine.kind == SubroutineKind::Method {
+            let class_name = self.class_name.clone();
+            self.symbols.define("this", &class_name, Kind::Argument);
+        }
 
-            return Ok(());
+        match &subroutine.return_type {
+            Some(type_name) => self.write_type(type_name)?,
+            None => self.write_keyword(Keyword::Void)?,
         }
+        self.write_identifier(&subroutine.name, Category::Subroutine, true)?;
 
-        let mut token = self.peek_token();
-        loop {
-            match token {
-                Token::Keyword(Keyword::Let) => self.write_let_statement()?,
-                Token::Keyword(Keyword::If) => self.write_if_statement()?,
-                Token::Keyword(Keyword::While) => self.write_while_statement()?,
-                Token::Keyword(Keyword::Do) => self.write_do_statement()?,
-                Token::Keyword(Keyword::Return) => self.write_return_statement()?,
-                _ => break,
-            };
+        self.write_symbol(&'(')?;
+        self.write_parameter_list(&subroutine.parameters)?;
+        self.write_symbol(&')')?;
 
-            token = self.peek_token();
-        }
-        self.write_closing_tag(b"</statements>")?;
+        self.write_subroutine_body(subroutine)?;
 
-        Ok(())
+        self.write_closing_tag(NonTerminals::SubroutineDec)
     }
 
-    fn write_let_statement(&mut self) -> Result<()> {
-        self.write_open_tag(b"<letStatement>")?;
+    fn write_parameter_list(&mut self, parameters: &[Parameter]) -> Result<()> {
+        self.write_open_tag(NonTerminals::ParameterList)?;
 
-        let token = self.next_token();
-        self.expect(&token, Token::Keyword(Keyword::Let));
-        self.write_terminal(&token)?;
-
-        let mut token = self.next_token();
-        if !matches!(&token, Token::Identifier(_)) {
-            panic!("Expected identifier, found {:?}", token);
+        for (index, parameter) in parameters.iter().enumerate() {
+            if index > 0 {
+                self.write_symbol(&',')?;
+            }
+            self.write_type(&parameter.type_name)?;
+            self.symbols.define(
+                &parameter.name,
+                &parameter.type_name.to_string(),
+                Kind::Argument,
+            );
+            self.write_identifier(&parameter.name, Category::Variable, true)?;
         }
-        self.write_terminal(&token)?;
 
-        token = self.next_token();
-        if matches!(&token, Token::Symbol(&'[')) {
-            self.write_terminal(&token)?;
-            self.write_expression()?;
+        self.write_closing_tag(NonTerminals::ParameterList)
+    }
 
-            token = self.next_token();
-            self.expect(&token, Token::Symbol(&']'));
-            self.write_terminal(&token)?;
+    fn write_subroutine_body(&mut self, subroutine: &SubroutineDec) -> Result<()> {
+        self.write_open_tag(NonTerminals::SubroutineBody)?;
 
-            token = self.next_token();
+        self.write_symbol(&'{')?;
+        for declaration in &subroutine.vars {
+            self.write_var_declaration(declaration)?;
         }
+        self.write_statements(&subroutine.statements)?;
+        self.write_symbol(&'}')?;
 
-        self.expect(&token, Token::Symbol(&'='));
-        self.write_terminal(&token)?;
-
-        self.write_expression()?;
-
-        token = self.next_token();
-        self.expect(&token, Token::Symbol(&';'));
-        self.write_terminal(&token)?;
-
-        self.write_closing_tag(b"</letStatement>")?;
-        Ok(())
+        self.write_closing_tag(NonTerminals::SubroutineBody)
     }
 
-    fn write_if_statement(&mut self) -> Result<()> {
-        self.write_open_tag(b"<ifStatement>")?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Keyword(Keyword::If));
-        self.write_terminal(&token)?;
+    fn write_var_declaration(&mut self, declaration: &VarDec) -> Result<()> {
+        self.write_open_tag(NonTerminals::VarDec)?;
 
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&'('));
-        self.write_terminal(&token)?;
+        self.write_keyword(Keyword::Var)?;
+        self.write_names(&declaration.type_name, &declaration.names, Kind::Local)?;
 
-        self.write_expression()?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&')'));
-        self.write_terminal(&token)?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&'{'));
-        self.write_terminal(&token)?;
+        self.write_closing_tag(NonTerminals::VarDec)
+    }
 
-        self.write_statements()?;
+    fn write_statements(&mut self, statements: &[Statement]) -> Result<()> {
+        self.write_open_tag(NonTerminals::Statements)?;
 
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&'}'));
-        self.write_terminal(&token)?;
+        for statement in statements {
+            self.write_statement(statement)?;
+        }
 
-        let token = self.peek_token();
-        if token == Token::Keyword(Keyword::Else) {
-            let token = self.next_token();
-            self.write_terminal(&token)?;
+        self.write_closing_tag(NonTerminals::Statements)
+    }
 
-            let token = self.next_token();
-            self.expect(&token, Token::Symbol(&'{'));
-            self.write_terminal(&token)?;
+    fn write_let_statement(&mut self, statement: &LetStatement) -> Result<()> {
+        self.write_open_tag(NonTerminals::LetStatement)?;
 
-            self.write_statements()?;
+        self.write_keyword(Keyword::Let)?;
+        self.write_identifier(&statement.name, Category::Variable, false)?;
 
-            let token = self.next_token();
-            self.expect(&token, Token::Symbol(&'}'));
-            self.write_terminal(&token)?;
+        if let Some(index) = &statement.index {
+            self.write_symbol(&'[')?;
+            self.write_expression(index)?;
+            self.write_symbol(&']')?;
         }
 
-        self.write_closing_tag(b"</ifStatement>")?;
+        self.write_symbol(&'=')?;
+        self.write_expression(&statement.value)?;
+        self.write_symbol(&';')?;
 
-        Ok(())
+        self.write_closing_tag(NonTerminals::LetStatement)
     }
 
-    fn write_while_statement(&mut self) -> Result<()> {
-        self.write_open_tag(b"<whileStatement>")?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Keyword(Keyword::While));
-        self.write_terminal(&token)?;
+    fn write_if_statement(&mut self, statement: &IfStatement) -> Result<()> {
+        self.write_open_tag(NonTerminals::IfStatement)?;
 
-        let mut token = self.next_token();
-        self.expect(&token, Token::Symbol(&'('));
-        self.write_terminal(&token)?;
+        self.write_keyword(Keyword::If)?;
+        self.write_condition(&statement.condition)?;
+        self.write_block(&statement.statements)?;
 
-        self.write_expression()?;
-
-        token = self.next_token();
-        self.expect(&token, Token::Symbol(&')'));
-        self.write_terminal(&token)?;
+        if let Some(otherwise) = &statement.otherwise {
+            self.write_keyword(Keyword::Else)?;
+            self.write_block(otherwise)?;
+        }
 
-        token = self.next_token();
-        self.expect(&token, Token::Symbol(&'{'));
-        self.write_terminal(&token)?;
+        self.write_closing_tag(NonTerminals::IfStatement)
+    }
 
-        self.write_statements()?;
+    fn write_while_statement(&mut self, statement: &WhileStatement) -> Result<()> {
+        self.write_open_tag(NonTerminals::WhileStatement)?;
 
-        token = self.next_token();
-        self.expect(&token, Token::Symbol(&'}'));
-        self.write_terminal(&token)?;
+        self.write_keyword(Keyword::While)?;
+        self.write_condition(&statement.condition)?;
+        self.write_block(&statement.statements)?;
 
-        self.write_closing_tag(b"</whileStatement>")?;
-        Ok(())
+        self.write_closing_tag(NonTerminals::WhileStatement)
     }
 
-    fn write_do_statement(&mut self) -> Result<()> {
-        self.write_open_tag(b"<doStatement>")?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Keyword(Keyword::Do));
-        self.write_terminal(&token)?;
-
-        loop {
-            let token = self.next_token();
-            self.write_terminal(&token)?;
+    fn write_do_statement(&mut self, call: &SubroutineCall) -> Result<()> {
+        self.write_open_tag(NonTerminals::DoStatement)?;
 
-            if matches!(token, Token::Symbol('(')) {
-                self.write_expression_list()?;
-            }
+        self.write_keyword(Keyword::Do)?;
+        self.write_subroutine_call(call)?;
+        self.write_symbol(&';')?;
 
-            if matches!(token, Token::Symbol(&';')) {
-                break;
-            }
-        }
-        self.write_closing_tag(b"</doStatement>")?;
-        Ok(())
+        self.write_closing_tag(NonTerminals::DoStatement)
     }
 
-    fn write_return_statement(&mut self) -> Result<()> {
-        self.write_open_tag(b"<returnStatement>")?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Keyword(Keyword::Return));
-        self.write_terminal(&token)?;
+    fn write_return_statement(&mut self, value: Option<&Expression>) -> Result<()> {
+        self.write_open_tag(NonTerminals::ReturnStatement)?;
 
-        self.write_expression()?;
-
-        let token = self.next_token();
-        self.expect(&token, Token::Symbol(&';'));
-        self.write_terminal(&token)?;
+        self.write_keyword(Keyword::Return)?;
+        if let Some(value) = value {
+            self.write_expression(value)?;
+        }
+        self.write_symbol(&';')?;
 
-        self.write_closing_tag(b"</returnStatement>")?;
-        Ok(())
+        self.write_closing_tag(NonTerminals::ReturnStatement)
     }
 
-    fn write_expression(&mut self) -> Result<()> {
-        let token = self.peek_token();
-
-        fn is_terminal(token: &Token) -> bool {
-            match token {
-                Token::IntConst(_) | Token::StringConst(_) => true,
-                Token::Keyword(Keyword::True)
-                | Token::Keyword(Keyword::False)
-                | Token::Keyword(Keyword::Null)
-                | Token::Keyword(Keyword::This) => true,
-                Token::Identifier(_) => true,
-                Token::Symbol(&'(') => true,
-                Token::Symbol(&'~') | Token::Symbol(&'-') => true,
-                Token::Symbol(&';') | Token::Symbol(&',') | Token::Symbol(&')') => false,
-                _ => false,
-            }
-        }
-
-        let is_term = is_terminal(&token);
-        if !is_term {
-            return Ok(());
-        }
+    fn write_expression(&mut self, expression: &Expression) -> Result<()> {
+        self.write_open_tag(NonTerminals::Expression)?;
 
-        self.write_open_tag(b"<expression>")?;
-
-        self.write_term()?;
-
-        let token = self.peek_token();
-        match token {
-            Token::Symbol(&'+')
-            | Token::Symbol(&'-')
-            | Token::Symbol(&'*')
-            | Token::Symbol(&'/')
-            | Token::Symbol(&'&')
-            | Token::Symbol(&'|')
-            | Token::Symbol(&'<')
-            | Token::Symbol(&'>')
-            | Token::Symbol(&'=') => {
-                let token = self.next_token();
-                self.write_terminal(&token)?;
-
-                self.write_term()?;
-            }
-            _ => {}
+        self.write_term(&expression.term)?;
+        for (op, term) in &expression.operations {
+            self.write_symbol(op.symbol())?;
+            self.write_term(term)?;
         }
 
-        self.write_closing_tag(b"</expression>")?;
-
-        Ok(())
+        self.write_closing_tag(NonTerminals::Expression)
     }
 
-    fn write_term(&mut self) -> Result<()> {
-        self.write_open_tag(b"<term>")?;
-
-        let token = self.next_token();
-        self.write_terminal(&token)?;
+    fn write_term(&mut self, term: &Term) -> Result<()> {
+        self.write_open_tag(NonTerminals::Term)?;
 
-        match token {
-            Token::Symbol(&'~') | Token::Symbol(&'-') => {
-                self.write_term()?;
+        match &term.kind {
+            TermKind::IntConst(value) => self.write_terminal(&Token::IntConst(*value))?,
+            TermKind::StringConst(string) => {
+                self.write_terminal(&Token::StringConst(string.clone()))?
             }
-            Token::Identifier(_) => {
-                let mut next = self.peek_token();
-
-                if next == Token::Symbol(&'[') {
-                    let token = self.next_token();
-                    self.write_terminal(&token)?;
-
-                    self.write_expression()?;
-
-                    let token = self.next_token();
-                    self.expect(&token, Token::Symbol(&']'));
-                    self.write_terminal(&token)?;
-                }
-
-                // subroutine call
-                if next == Token::Symbol(&'.') {
-                    let token = self.next_token();
-                    self.write_terminal(&token)?;
-
-                    let token = self.next_token();
-                    if !matches!(token, Token::Identifier(_)) {
-                        panic!("Expected identifier, found {:?}", token);
-                    }
-                    self.write_terminal(&token)?;
-
-                    next = self.peek_token();
-                }
-
-                // Need to be last
-                if next == Token::Symbol(&'(') {
-                    let token = self.next_token();
-                    self.write_terminal(&token)?;
-
-                    self.write_expression_list()?;
-
-                    let token = self.next_token();
-                    self.expect(&token, Token::Symbol(&')'));
-                    self.write_terminal(&token)?;
-                }
+            TermKind::Keyword(keyword) => self.write_keyword(match keyword {
+                KeywordConst::True => Keyword::True,
+                KeywordConst::False => Keyword::False,
+                KeywordConst::Null => Keyword::Null,
+                KeywordConst::This => Keyword::This,
+            })?,
+            TermKind::Var(name) => self.write_identifier(name, Category::Variable, false)?,
+            TermKind::Index(name, index) => {
+                self.write_identifier(name, Category::Variable, false)?;
+                self.write_symbol(&'[')?;
+                self.write_expression(index)?;
+                self.write_symbol(&']')?;
             }
-            Token::Symbol(&'(') => {
-                self.write_expression()?;
-
-                let token = self.next_token();
-                self.expect(&token, Token::Symbol(&')'));
-                self.write_terminal(&token)?;
+            TermKind::Call(call) => self.write_subroutine_call(call)?,
+            TermKind::Parenthesized(expression) => {
+                self.write_symbol(&'(')?;
+                self.write_expression(expression)?;
+                self.write_symbol(&')')?;
+            }
+            TermKind::Unary(op, term) => {
+                self.write_symbol(op.symbol())?;
+                self.write_term(term)?;
             }
-            _ => {}
         }
 
-        self.write_closing_tag(b"</term>")?;
-
-        Ok(())
+        self.write_closing_tag(NonTerminals::Term)
     }
 
-    fn write_expression_list(&mut self) -> Result<()> {
-        self.write_open_tag(b"<expressionList>")?;
+    fn write_subroutine_call(&mut self, call: &SubroutineCall) -> Result<()> {
+        if let Some(receiver) = &call.receiver {
+            let category = if self.symbols.get(receiver).is_some() {
+                Category::Variable
+            } else {
+                Category::Class
+            };
+            self.write_identifier(receiver, category, false)?;
+            self.write_symbol(&'.')?;
+        }
+        self.write_identifier(&call.name, Category::Subroutine, false)?;
 
-        loop {
-            let mut token = self.peek_token();
-            if matches!(token, Token::Symbol(')')) {
-                break;
-            }
+        self.write_symbol(&'(')?;
+        self.write_expression_list(&call.arguments)?;
+        self.write_symbol(&')')
+    }
 
-            self.write_expression()?;
+    fn write_expression_list(&mut self, expressions: &[Expression]) -> Result<()> {
+        self.write_open_tag(NonTerminals::ExpressionList)?;
 
-            token = self.peek_token();
-            if token == Token::Symbol(&',') {
-                token = self.next_token();
-                self.write_terminal(&token)?;
+        for (index, expression) in expressions.iter().enumerate() {
+            if index > 0 {
+                self.write_symbol(&',')?;
             }
+            self.write_expression(expression)?;
         }
 
-        self.write_closing_tag(b"</expressionList>")?;
-
-        Ok(())
+        self.write_closing_tag(NonTerminals::ExpressionList)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compilation_engine::CompilationEngine;
 
     #[test]
-    fn test_expect() {
-        let b = "class Main {}".as_bytes();
-        let mut wb = [0u8; 1024];
-        let mut wb = wb.as_mut_slice();
-        let mut c = CompilationEngine::new(b);
-        let w = XmlWriter::new(&mut c, &mut wb);
+    fn writes_parse_tree() {
+        let class = CompilationEngine::new(
+            "class Main { function void main() { do Output.printInt(-x[1]); return; } }".as_bytes(),
+        )
+        .parse()
+        .unwrap();
+        let mut output = vec![];
+        XmlWriter::new(&mut output).write_class(&class).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let expected = "<doStatement>
+  <keyword>do</keyword>
+  <identifier>Output</identifier>
+  <symbol>.</symbol>
+  <identifier>printInt</identifier>
+  <symbol>(</symbol>
+  <expressionList>
+    <expression>
+      <term>
+        <symbol>-</symbol>
+        <term>
+          <identifier>x</identifier>
+          <symbol>[</symbol>
+          <expression>
+            <term>
+              <integerConstant>1</integerConstant>
+            </term>
+          </expression>
+          <symbol>]</symbol>
+        </term>
+      </term>
+    </expression>
+  </expressionList>
+  <symbol>)</symbol>
+  <symbol>;</symbol>
+</doStatement>";
+        let start = output
+            .lines()
+            .position(|line| line.trim() == "<doStatement>")
+            .unwrap();
+        let lines: Vec<&str> = output.lines().skip(start).take(26).collect();
+        let expected: Vec<String> = expected
+            .lines()
+            .map(|line| format!("{}{line}", " ".repeat(8)))
+            .collect();
+
+        assert_eq!(lines, expected);
+    }
 
-        w.expect(&Token::Keyword(Keyword::If), Token::Keyword(Keyword::If));
-        w.expect(&Token::IntConst(1), Token::IntConst(1));
-        w.expect(&Token::Symbol(&'"'), Token::Symbol(&'"'));
+    #[test]
+    fn writes_compatible_parse_tree() {
+        let class = CompilationEngine::new(
+            "class A { method void f() { while (x < 1) {} return; } }".as_bytes(),
+        )
+        .parse()
+        .unwrap();
+        let mut output = vec![];
+        XmlWriter::new(&mut output)
+            .compatible(true)
+            .write_class(&class)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<class>
+  <keyword> class </keyword>
+  <identifier> A </identifier>
+  <symbol> { </symbol>
+  <subroutineDec>
+    <keyword> method </keyword>
+    <keyword> void </keyword>
+    <identifier> f </identifier>
+    <symbol> ( </symbol>
+    <parameterList>
+    </parameterList>
+    <symbol> ) </symbol>
+    <subroutineBody>
+      <symbol> { </symbol>
+      <statements>
+        <whileStatement>
+          <keyword> while </keyword>
+          <symbol> ( </symbol>
+          <expression>
+            <term>
+              <identifier> x </identifier>
+            </term>
+            <symbol> &lt; </symbol>
+            <term>
+              <integerConstant> 1 </integerConstant>
+            </term>
+          </expression>
+          <symbol> ) </symbol>
+          <symbol> { </symbol>
+          <statements>
+          </statements>
+          <symbol> } </symbol>
+        </whileStatement>
+        <returnStatement>
+          <keyword> return </keyword>
+          <symbol> ; </symbol>
+        </returnStatement>
+      </statements>
+      <symbol> } </symbol>
+    </subroutineBody>
+  </subroutineDec>
+  <symbol> } </symbol>
+</class>
+"
+        );
+    }
+
+    #[test]
+    fn escapes_string_constants() {
+        let class = CompilationEngine::new(
+            "class A { function void f() { do g(\"<a & b>\"); return; } }".as_bytes(),
+        )
+        .parse()
+        .unwrap();
+        let mut output = vec![];
+        XmlWriter::new(&mut output).write_class(&class).unwrap();
+
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("<stringConstant>&lt;a &amp; b&gt;</stringConstant>\n"));
     }
 
     #[test]
-    #[should_panic]
-    fn test_expect_panics() {
-        let b = "class Main {}".as_bytes();
-        let mut wb = [0u8; 1024];
-        let mut wb = wb.as_mut_slice();
-        let mut c = CompilationEngine::new(b);
-        let w = XmlWriter::new(&mut c, &mut wb);
-
-        w.expect(&Token::Keyword(Keyword::If), Token::Keyword(Keyword::Do));
+    fn writes_operator_chain_in_one_expression() {
+        let class = CompilationEngine::new(
+            "class Main { function int f() { return a + b - c; } }".as_bytes(),
+        )
+        .parse()
+        .unwrap();
+        let mut output = vec![];
+        XmlWriter::new(&mut output).write_class(&class).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let expression: Vec<&str> = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "<expression>")
+            .take_while(|line| *line != "</expression>")
+            .collect();
+
+        assert_eq!(
+            expression,
+            vec![
+                "<expression>",
+                "<term>",
+                "<identifier>a</identifier>",
+                "</term>",
+                "<symbol>+</symbol>",
+                "<term>",
+                "<identifier>b</identifier>",
+                "</term>",
+                "<symbol>-</symbol>",
+                "<term>",
+                "<identifier>c</identifier>",
+                "</term>",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotates_identifiers_with_symbols() {
+        let source = "class Point {
+            field int x;
+            method void set(Point other) {
+                var int y;
+                let x = other.get(y);
+                do Output.printInt(x);
+                return;
+            }
+        }";
+        let class = CompilationEngine::new(source.as_bytes()).parse().unwrap();
+        let mut output = vec![];
+        XmlWriter::new(&mut output)
+            .annotate(true)
+            .write_class(&class)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let identifiers: Vec<&str> = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("<identifier"))
+            .collect();
+
+        assert_eq!(
+            identifiers,
+            vec![
+                r#"<identifier category="class" usage="defined">Point</identifier>"#,
+                r#"<identifier category="variable" kind="field" type="int" index="0" usage="defined">x</identifier>"#,
+                r#"<identifier category="subroutine" usage="defined">set</identifier>"#,
+                r#"<identifier category="class" usage="used">Point</identifier>"#,
+                r#"<identifier category="variable" kind="argument" type="Point" index="1" usage="defined">other</identifier>"#,
+                r#"<identifier category="variable" kind="var" type="int" index="0" usage="defined">y</identifier>"#,
+                r#"<identifier category="variable" kind="field" type="int" index="0" usage="used">x</identifier>"#,
+                r#"<identifier category="variable" kind="argument" type="Point" index="1" usage="used">other</identifier>"#,
+                r#"<identifier category="subroutine" usage="used">get</identifier>"#,
+                r#"<identifier category="variable" kind="var" type="int" index="0" usage="used">y</identifier>"#,
+                r#"<identifier category="class" usage="used">Output</identifier>"#,
+                r#"<identifier category="subroutine" usage="used">printInt</identifier>"#,
+                r#"<identifier category="variable" kind="field" type="int" index="0" usage="used">x</identifier>"#,
+            ]
+        );
     }
 }
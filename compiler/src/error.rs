@@ -0,0 +1,146 @@
+use std::{fmt, io};
+
+use crate::tokenizer::Span;
+
+/// Unexpected token or character in Jack source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub span: Span,
+    /// Everything accepted at `span`, e.g. "`;`" or "expression"
+    pub expected: Vec<String>,
+    /// What was found instead, e.g. "`}`" or "end of file"
+    pub found: String,
+    /// Source line containing `span`, shown with a caret under the error
+    pub source_line: String,
+}
+
+impl SyntaxError {
+    /// `a`, `a or b`, `a, b or c`
+    fn expected_list(&self) -> String {
+        match self.expected.as_slice() {
+            [] => String::new(),
+            [single] => single.clone(),
+            [init @ .., last] => format!("{} or {last}", init.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Span { line, column } = self.span;
+        if self.expected.is_empty() {
+            writeln!(f, "line {line}, column {column}: unexpected {}", self.found)?;
+        } else {
+            writeln!(
+                f,
+                "line {line}, column {column}: expected {}, found {}",
+                self.expected_list(),
+                self.found
+            )?;
+        }
+
+        // Tabs are kept so the caret lines up with the source however it is displayed
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let gutter = " ".repeat(line.to_string().len());
+
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line} | {}", self.source_line)?;
+        write!(f, "{gutter} | {padding}^")
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// All syntax errors found in one source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxErrors(pub Vec<SyntaxError>);
+
+impl fmt::Display for SyntaxErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, err) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "\n\n")?;
+            }
+            write!(f, "{err}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for SyntaxErrors {}
+
+impl From<SyntaxErrors> for io::Error {
+    fn from(errors: SyntaxErrors) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, errors)
+    }
+}
+
+/// Valid syntax with invalid meaning, e.g. use of undeclared variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Span { line, column } = self.span;
+        write!(f, "line {line}, column {column}: {}", self.message)
+    }
+}
+
+impl std::error::Error for SemanticError {}
+
+/// All semantic errors found in one class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticErrors(pub Vec<SemanticError>);
+
+impl fmt::Display for SemanticErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, err) in self.0.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{err}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for SemanticErrors {}
+
+impl From<SemanticErrors> for io::Error {
+    fn from(errors: SemanticErrors) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_source_line_with_caret() {
+        let err = SyntaxError {
+            span: Span {
+                line: 12,
+                column: 16,
+            },
+            expected: vec!["`;`".to_owned(), "`,`".to_owned(), "operator".to_owned()],
+            found: "`}`".to_owned(),
+            source_line: "\tlet x = 1 + 2 }".to_owned(),
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "line 12, column 16: expected `;`, `,` or operator, found `}`\n   |\n12 | \tlet x = 1 + 2 }\n   | \t              ^"
+        );
+    }
+}
@@ -1,3 +1,8 @@
 pub mod analyzer;
+pub mod ast;
+pub mod comparer;
 pub mod compilation_engine;
+pub mod error;
+pub mod semantic;
+pub mod symbol_table;
 pub mod tokenizer;
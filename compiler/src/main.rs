@@ -1,22 +1,83 @@
 use std::{
     io::{self, Result},
     path::Path,
+    process,
 };
 
-use compiler::analyzer::Analyzer;
+use compiler::{
+    analyzer::{Analyzer, Emit},
+    comparer,
+};
 
-fn main() -> Result<()> {
-    let mut args = std::env::args();
+fn main() {
+    // Syntax errors span several lines, so they are printed with `Display` rather than `Debug`
+    if let Err(err) = run() {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
 
-    if args.len() < 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "no filename passed!"));
+fn run() -> Result<()> {
+    let mut emit = Emit::ParseTree;
+    let mut annotate = false;
+    let mut compatible = false;
+    let mut compare = false;
+    let mut output_dir = None;
+    let mut paths = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--annotate" => annotate = true,
+            "--compatible" => compatible = true,
+            "--compare" => compare = true,
+            "--emit" => emit = emit_mode(args.next())?,
+            flag if flag.starts_with("--emit=") => {
+                emit = emit_mode(Some(flag["--emit=".len()..].to_owned()))?
+            }
+            "-o" => match args.next() {
+                Some(dir) => output_dir = Some(dir),
+                None => return Err(io::Error::other("-o needs a directory")),
+            },
+            flag if flag.starts_with('-') => {
+                return Err(io::Error::other(format!("unknown option: {flag}")));
+            }
+            _ => paths.push(arg),
+        }
     }
 
-    if args.len() > 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "too many arguments"));
+    // `--compare expected.xml output.xml` checks output instead of compiling
+    if compare {
+        let [expected, found] = paths.as_slice() else {
+            return Err(io::Error::other(
+                "--compare needs expected and output files",
+            ));
+        };
+
+        return match comparer::compare_files(expected.as_ref(), found.as_ref())? {
+            None => {
+                println!("Comparison ended successfully");
+                Ok(())
+            }
+            Some(mismatch) => Err(io::Error::other(format!(
+                "{found} differs from {expected}: {mismatch}"
+            ))),
+        };
     }
 
-    let file_path = args.nth(1).unwrap();
+    if paths.is_empty() {
+        return Err(io::Error::other("no filename passed!"));
+    }
+
+    if paths.len() > 1 {
+        return Err(io::Error::other("too many arguments"));
+    }
+
+    if annotate && emit != Emit::ParseTree {
+        return Err(io::Error::other("--annotate works only with --emit xml"));
+    }
+
+    let file_path = paths.remove(0);
     let file_path: &Path = file_path.as_ref();
 
     let mut files = Vec::with_capacity(10);
@@ -24,20 +85,22 @@ fn main() -> Result<()> {
     {
         files.push(file_path.to_owned());
     } else if file_path.is_dir() {
-        for file in file_path.read_dir().expect("failed to read directory") {
-            if let Ok(entry) = file {
-                if let Some(extention) = entry.path().extension() {
-                    if extention == "jack"
-                        && !entry
-                            .path()
-                            .file_name()
-                            .expect("Must have filename")
-                            .to_str()
-                            .expect("Must convert to str normally")
-                            .starts_with(".")
-                    {
-                        files.push(entry.path());
-                    }
+        for entry in file_path
+            .read_dir()
+            .expect("failed to read directory")
+            .flatten()
+        {
+            if let Some(extention) = entry.path().extension() {
+                if extention == "jack"
+                    && !entry
+                        .path()
+                        .file_name()
+                        .expect("Must have filename")
+                        .to_str()
+                        .expect("Must convert to str normally")
+                        .starts_with(".")
+                {
+                    files.push(entry.path());
                 }
             }
         }
@@ -46,7 +109,23 @@ fn main() -> Result<()> {
         panic!("Provided string neigher path nor file");
     }
 
-    Analyzer::process(files.as_slice())
+    let mut analyzer = Analyzer::new(emit)
+        .annotate(annotate)
+        .compatible(compatible);
+    if let Some(dir) = output_dir {
+        analyzer = analyzer.output_dir(dir);
+    }
+
+    analyzer.run(files.as_slice())
+}
+
+/// `--emit` value: `tokens`, `xml`, `vm` or `json`
+fn emit_mode(name: Option<String>) -> Result<Emit> {
+    let name = name.ok_or_else(|| io::Error::other("--emit needs a mode"))?;
 
-    // Ok(())
+    Emit::from_name(&name).ok_or_else(|| {
+        io::Error::other(format!(
+            "unknown --emit mode `{name}`, expected tokens, xml, vm or json"
+        ))
+    })
 }
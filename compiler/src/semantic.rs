@@ -0,0 +1,478 @@
+//! Checks meaning of parsed classes before code generation.
+//!
+//! Finds errors the VM emulator would otherwise crash on: undeclared names,
+//! `this` and fields in functions, methods called without an object, `return`
+//! not matching the return type and wrong argument counts of calls
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::*,
+    error::SemanticError,
+    symbol_table::{Kind, SymbolTable},
+    tokenizer::Span,
+};
+
+/// Classes of the Jack OS, available to every program
+pub const OS_CLASSES: [&str; 8] = [
+    "Array", "Keyboard", "Math", "Memory", "Output", "Screen", "String", "Sys",
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Signature {
+    kind: SubroutineKind,
+    parameters: usize,
+}
+
+/// Classes compiled together, which calls and types are resolved against
+#[derive(Debug, Default)]
+pub struct Program {
+    /// Subroutines of parsed classes, by class and subroutine name
+    subroutines: HashMap<String, HashMap<String, Signature>>,
+    /// Classes declared elsewhere, whose subroutines are not checked
+    declared: HashSet<String>,
+}
+
+impl Program {
+    /// Program of `classes` and the OS classes
+    pub fn new<'a>(classes: impl IntoIterator<Item = &'a Class>) -> Self {
+        let subroutines = classes
+            .into_iter()
+            .map(|class| {
+                let signatures = class
+                    .subroutines
+                    .iter()
+                    .map(|subroutine| {
+                        let signature = Signature {
+                            kind: subroutine.kind,
+                            parameters: subroutine.parameters.len(),
+                        };
+                        (subroutine.name.clone(), signature)
+                    })
+                    .collect();
+                (class.name.clone(), signatures)
+            })
+            .collect();
+
+        Program {
+            subroutines,
+            declared: OS_CLASSES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// Declares class compiled separately, e.g. other `.jack` file of the same directory
+    pub fn declare(&mut self, class: &str) {
+        self.declared.insert(class.to_owned());
+    }
+
+    fn is_class(&self, name: &str) -> bool {
+        self.subroutines.contains_key(name) || self.declared.contains(name)
+    }
+
+    /// Returns every semantic error in `class`, which has to be part of the program
+    pub fn check(&self, class: &Class) -> Vec<SemanticError> {
+        let mut checker = Checker {
+            program: self,
+            class: &class.name,
+            symbols: SymbolTable::new(),
+            subroutine: String::new(),
+            kind: SubroutineKind::Function,
+            errors: vec![],
+        };
+        checker.check_class(class);
+
+        checker.errors
+    }
+}
+
+struct Checker<'a> {
+    program: &'a Program,
+    class: &'a str,
+    symbols: SymbolTable,
+    /// Full name of checked subroutine, e.g. `Main.main`
+    subroutine: String,
+    kind: SubroutineKind,
+    errors: Vec<SemanticError>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(SemanticError { span, message });
+    }
+
+    fn check_type(&mut self, type_name: &Type, span: Span) {
+        if let Type::Class(name) = type_name {
+            if !self.program.is_class(name) {
+                self.error(span, format!("undeclared class `{name}`"));
+            }
+        }
+    }
+
+    fn define(&mut self, names: &[String], type_name: &Type, kind: Kind, span: Span) {
+        self.check_type(type_name, span);
+        for name in names {
+            if !self.symbols.define(name, &type_name.to_string(), kind) {
+                self.error(span, format!("`{name}` is already defined"));
+            }
+        }
+    }
+
+    fn check_class(&mut self, class: &Class) {
+        for declaration in &class.vars {
+            let kind = match declaration.kind {
+                ClassVarKind::Static => Kind::Static,
+                ClassVarKind::Field => Kind::Field,
+            };
+            self.define(
+                &declaration.names,
+                &declaration.type_name,
+                kind,
+                declaration.span,
+            );
+        }
+
+        for subroutine in &class.subroutines {
+            self.check_subroutine(subroutine);
+        }
+    }
+
+    fn check_subroutine(&mut self, subroutine: &SubroutineDec) {
+        self.subroutine = format!("{}.{}", self.class, subroutine.name);
+        self.kind = subroutine.kind;
+        self.symbols.start_subroutine();
+        if subroutine.kind == SubroutineKind::Method {
+            self.symbols.define("this", self.class, Kind::Argument);
+        }
+
+        if let Some(return_type) = &subroutine.return_type {
+            self.check_type(return_type, subroutine.span);
+        }
+        for parameter in &subroutine.parameters {
+            let names = [parameter.name.clone()];
+            self.define(&names, &parameter.type_name, Kind::Argument, parameter.span);
+        }
+        for declaration in &subroutine.vars {
+            self.define(
+                &declaration.names,
+                &declaration.type_name,
+                Kind::Local,
+                declaration.span,
+            );
+        }
+
+        self.check_statements(&subroutine.statements, &subroutine.return_type);
+    }
+
+    fn check_statements(&mut self, statements: &[Statement], return_type: &Option<Type>) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if self.symbols.get(&let_statement.name).is_none() {
+                        self.error(
+                            statement.span,
+                            format!("assignment to undeclared variable `{}`", let_statement.name),
+                        );
+                    } else {
+                        self.check_field_access(&let_statement.name, statement.span);
+                    }
+                    if let Some(index) = &let_statement.index {
+                        self.check_expression(index);
+                    }
+                    self.check_expression(&let_statement.value);
+                }
+                StatementKind::If(if_statement) => {
+                    self.check_expression(&if_statement.condition);
+                    self.check_statements(&if_statement.statements, return_type);
+                    if let Some(otherwise) = &if_statement.otherwise {
+                        self.check_statements(otherwise, return_type);
+                    }
+                }
+                StatementKind::While(while_statement) => {
+                    self.check_expression(&while_statement.condition);
+                    self.check_statements(&while_statement.statements, return_type);
+                }
+                StatementKind::Do(call) => self.check_call(call),
+                StatementKind::Return(value) => {
+                    match (return_type, value) {
+                        (None, Some(_)) => self.error(
+                            statement.span,
+                            format!("void subroutine `{}` returns a value", self.subroutine),
+                        ),
+                        (Some(type_name), None) => self.error(
+                            statement.span,
+                            format!(
+                                "`{}` returns without a value of type `{type_name}`",
+                                self.subroutine
+                            ),
+                        ),
+                        _ => {}
+                    }
+                    if let Some(value) = value {
+                        self.check_expression(value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_expression(&mut self, expression: &Expression) {
+        self.check_term(&expression.term);
+        for (_, term) in &expression.operations {
+            self.check_term(term);
+        }
+    }
+
+    fn check_term(&mut self, term: &Term) {
+        match &term.kind {
+            TermKind::IntConst(_) | TermKind::StringConst(_) => {}
+            TermKind::Keyword(KeywordConst::This) => {
+                if self.kind == SubroutineKind::Function {
+                    self.error(
+                        term.span,
+                        format!("`this` used in function `{}`", self.subroutine),
+                    );
+                }
+            }
+            TermKind::Keyword(_) => {}
+            TermKind::Var(name) => self.check_variable(name, term.span),
+            TermKind::Index(name, index) => {
+                self.check_variable(name, term.span);
+                self.check_expression(index);
+            }
+            TermKind::Call(call) => self.check_call(call),
+            TermKind::Parenthesized(expression) => self.check_expression(expression),
+            TermKind::Unary(_, term) => self.check_term(term),
+        }
+    }
+
+    fn check_variable(&mut self, name: &str, span: Span) {
+        if self.symbols.get(name).is_none() {
+            self.error(span, format!("undeclared variable `{name}`"));
+        } else {
+            self.check_field_access(name, span);
+        }
+    }
+
+    /// Fields live in `this`, which functions do not have
+    fn check_field_access(&mut self, name: &str, span: Span) {
+        let is_field = self
+            .symbols
+            .get(name)
+            .is_some_and(|symbol| symbol.kind == Kind::Field);
+        if is_field && self.kind == SubroutineKind::Function {
+            self.error(
+                span,
+                format!("field `{name}` used in function `{}`", self.subroutine),
+            );
+        }
+    }
+
+    fn check_call(&mut self, call: &SubroutineCall) {
+        for argument in &call.arguments {
+            self.check_expression(argument);
+        }
+
+        // Class of called subroutine and whether it is called on an object
+        let (class, with_object) = match &call.receiver {
+            None => {
+                let with_object = self.kind != SubroutineKind::Function;
+                (self.class.to_owned(), with_object)
+            }
+            Some(receiver) => match self.symbols.get(receiver) {
+                Some(symbol) => {
+                    let class = symbol.type_name.clone();
+                    self.check_field_access(receiver, call.span);
+                    if matches!(class.as_str(), "int" | "char" | "boolean") {
+                        self.error(
+                            call.span,
+                            format!("`{receiver}` of type `{class}` has no subroutines"),
+                        );
+                        return;
+                    }
+                    (class, true)
+                }
+                None if self.program.is_class(receiver) => (receiver.clone(), false),
+                None => {
+                    self.error(
+                        call.span,
+                        format!("undeclared variable or class `{receiver}`"),
+                    );
+                    return;
+                }
+            },
+        };
+
+        // Only subroutines of classes compiled together are known
+        let Some(subroutines) = self.program.subroutines.get(&class) else {
+            return;
+        };
+        let name = format!("{class}.{}", call.name);
+        let Some(signature) = subroutines.get(&call.name).copied() else {
+            self.error(call.span, format!("undefined subroutine `{name}`"));
+            return;
+        };
+
+        if signature.kind == SubroutineKind::Method && !with_object {
+            let message = match call.receiver {
+                None => format!(
+                    "method `{name}` called from function `{}` without an object",
+                    self.subroutine
+                ),
+                Some(_) => format!("method `{name}` called without an object"),
+            };
+            self.error(call.span, message);
+        }
+        if signature.parameters != call.arguments.len() {
+            self.error(
+                call.span,
+                format!(
+                    "`{name}` expects {} arguments, found {}",
+                    signature.parameters,
+                    call.arguments.len()
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation_engine::CompilationEngine;
+
+    fn parse(source: &str) -> Class {
+        CompilationEngine::new(source.as_bytes()).parse().unwrap()
+    }
+
+    /// Errors of the first class, checked together with the rest
+    fn errors(sources: &[&str]) -> Vec<String> {
+        let classes: Vec<_> = sources.iter().map(|source| parse(source)).collect();
+
+        Program::new(&classes)
+            .check(&classes[0])
+            .iter()
+            .map(|err| err.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_program() {
+        let main = "class Main {
+            function void main() {
+                var Point p; var Array a;
+                let p = Point.new(1, 2);
+                let a = Array.new(p.getX());
+                do Output.printInt(p.getX() + a[0]);
+                return;
+            }
+        }";
+        let point = "class Point {
+            field int x, y;
+            static int count;
+            constructor Point new(int ax, int ay) {
+                let x = ax; let y = ay; let count = count + 1;
+                do print();
+                return this;
+            }
+            method int getX() { return x; }
+            method void print() { do Output.printInt(x); return; }
+        }";
+
+        assert_eq!(errors(&[main, point]), Vec::<String>::new());
+        assert_eq!(errors(&[point, main]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_undeclared_names() {
+        let source = "class Main {
+            field Foo f;
+            function void main() {
+                var int x;
+                let y = x + z;
+                do Bar.run();
+                do x.run();
+                return;
+            }
+        }";
+
+        assert_eq!(
+            errors(&[source]),
+            [
+                "line 2, column 13: undeclared class `Foo`",
+                "line 5, column 17: assignment to undeclared variable `y`",
+                "line 5, column 29: undeclared variable `z`",
+                "line 6, column 17: undeclared variable or class `Bar`",
+                "line 7, column 17: `x` of type `int` has no subroutines",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_object_use_in_functions() {
+        let source = "class Main {
+            field int x;
+            function void main() {
+                do draw();
+                do Main.draw();
+                let x = 1;
+                do Output.printInt(this);
+                return;
+            }
+            method void draw() { let x = 2; do draw(); return; }
+        }";
+
+        assert_eq!(
+            errors(&[source]),
+            [
+                "line 4, column 17: method `Main.draw` called from function `Main.main` without an object",
+                "line 5, column 17: method `Main.draw` called without an object",
+                "line 6, column 17: field `x` used in function `Main.main`",
+                "line 7, column 36: `this` used in function `Main.main`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_mismatched_returns() {
+        let source = "class Main {
+            function void main() { return 1; }
+            function int f() { if (true) { return; } return 1; }
+            constructor Main new() { return; }
+        }";
+
+        assert_eq!(
+            errors(&[source]),
+            [
+                "line 2, column 36: void subroutine `Main.main` returns a value",
+                "line 3, column 44: `Main.f` returns without a value of type `int`",
+                "line 4, column 38: `Main.new` returns without a value of type `Main`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_argument_counts_and_unknown_subroutines() {
+        let main = "class Main {
+            function void main() {
+                var Point p;
+                let p = Point.new(1);
+                do p.move(1, 2, 3);
+                do p.jump();
+                do Output.printString(1, 2);
+                return;
+            }
+        }";
+        let point = "class Point {
+            constructor Point new(int x, int y) { return this; }
+            method void move(int dx, int dy) { return; }
+        }";
+
+        assert_eq!(
+            errors(&[main, point]),
+            [
+                "line 4, column 25: `Point.new` expects 2 arguments, found 1",
+                "line 5, column 17: `Point.move` expects 2 arguments, found 3",
+                "line 6, column 17: undefined subroutine `Point.jump`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, fmt};
+
+use translator::vm::Segment;
+
+/// Kind of Jack variable, which defines its scope and VM segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Static,
+    Field,
+    Argument,
+    Local,
+}
+
+impl Kind {
+    /// VM segment holding variables of this kind
+    pub fn segment(&self) -> Segment {
+        match self {
+            Kind::Static => Segment::Static,
+            Kind::Field => Segment::This,
+            Kind::Argument => Segment::Argument,
+            Kind::Local => Segment::Local,
+        }
+    }
+}
+
+/// Jack name of kind, `var` for locals
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Static => "static",
+            Kind::Field => "field",
+            Kind::Argument => "argument",
+            Kind::Local => "var",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub type_name: String,
+    pub kind: Kind,
+    /// Index in VM segment of `kind`
+    pub index: u16,
+}
+
+/// Variables visible while compiling a subroutine.
+///
+/// Class scope holds `static` and `field` variables, subroutine scope holds
+/// arguments and locals and shadows class scope
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    class: HashMap<String, Symbol>,
+    subroutine: HashMap<String, Symbol>,
+    counts: HashMap<Kind, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clears subroutine scope before compiling next subroutine
+    pub fn start_subroutine(&mut self) {
+        self.subroutine.clear();
+        self.counts.remove(&Kind::Argument);
+        self.counts.remove(&Kind::Local);
+    }
+
+    /// Adds variable with next free index of its kind.
+    /// Returns `false` if variable is already defined in the same scope
+    pub fn define(&mut self, name: &str, type_name: &str, kind: Kind) -> bool {
+        let count = self.counts.entry(kind).or_default();
+        let symbol = Symbol {
+            type_name: type_name.to_owned(),
+            kind,
+            index: *count,
+        };
+
+        let scope = match kind {
+            Kind::Static | Kind::Field => &mut self.class,
+            Kind::Argument | Kind::Local => &mut self.subroutine,
+        };
+        if scope.contains_key(name) {
+            return false;
+        }
+
+        *count += 1;
+        scope.insert(name.to_owned(), symbol);
+        true
+    }
+
+    /// Number of variables of `kind` defined in current scope
+    pub fn var_count(&self, kind: Kind) -> u16 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.subroutine.get(name).or_else(|| self.class.get(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subroutine_scope_shadows_class_scope() {
+        let mut table = SymbolTable::new();
+        table.define("x", "int", Kind::Field);
+        table.define("y", "int", Kind::Field);
+        table.define("count", "int", Kind::Static);
+
+        table.start_subroutine();
+        table.define("this", "Point", Kind::Argument);
+        table.define("x", "boolean", Kind::Local);
+
+        assert_eq!(table.get("x").unwrap().kind, Kind::Local);
+        assert_eq!(table.get("y").unwrap().index, 1);
+        assert_eq!(table.var_count(Kind::Field), 2);
+
+        table.start_subroutine();
+        assert_eq!(table.get("x").unwrap().kind, Kind::Field);
+        assert_eq!(table.var_count(Kind::Argument), 0);
+    }
+
+    #[test]
+    fn rejects_redefinition_in_same_scope() {
+        let mut table = SymbolTable::new();
+
+        assert!(table.define("a", "int", Kind::Local));
+        assert!(!table.define("a", "char", Kind::Argument));
+        assert_eq!(table.var_count(Kind::Argument), 0);
+    }
+}
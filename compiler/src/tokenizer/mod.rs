@@ -1,24 +1,32 @@
 pub mod xml_writer;
 
-use core::panic;
-use std::io::{BufRead, BufReader, Result};
+use core::fmt;
+use std::io::{BufReader, Read};
+
+use crate::error::SyntaxError;
 
 static SYMBOL_LIST: [char; 19] = [
     '(', ')', '{', '}', '[', ']', '.', ',', ';', '+', '-', '*', '/', '&', '|', '<', '>', '=', '~',
 ];
 
-/// Using this instead of `str.trim()` because
-/// it trims string without realocation in place
-#[inline]
-fn trim(s: &mut String) {
-    let trimmed = s.trim_end();
-    s.truncate(trimmed.len());
+struct CharsNumber(usize);
+
+/// Largest integer constant allowed by Jack
+const MAX_INT: u16 = 32767;
 
-    let trimmed = s.trim_start();
-    s.replace_range(..(s.len() - trimmed.len()), "");
+/// Invalid token, dropping `skip` of source lets tokenizing go on after it
+struct LexError {
+    skip: CharsNumber,
+    expected: Vec<String>,
+    found: String,
 }
 
-struct CharsNumber(usize);
+/// Location of token in source, lines and columns are numbered from 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
@@ -29,6 +37,19 @@ pub enum Token {
     StringConst(String),
 }
 
+/// Token as written in source
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "{keyword}"),
+            Token::Symbol(symbol) => write!(f, "{symbol}"),
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::IntConst(value) => write!(f, "{value}"),
+            Token::StringConst(string) => write!(f, "\"{string}\""),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Keyword {
     Class,
@@ -54,122 +75,214 @@ pub enum Keyword {
     This,
 }
 
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Keyword::*;
+        let keyword = match self {
+            Class => "class",
+            Method => "method",
+            Function => "function",
+            Constructor => "constructor",
+            Int => "int",
+            Boolean => "boolean",
+            Char => "char",
+            Void => "void",
+            Var => "var",
+            Static => "static",
+            Field => "field",
+            Let => "let",
+            Do => "do",
+            If => "if",
+            Else => "else",
+            While => "while",
+            Return => "return",
+            True => "true",
+            False => "false",
+            Null => "null",
+            This => "this",
+        };
+
+        write!(f, "{keyword}")
+    }
+}
+
 #[derive(Debug)]
-pub struct Tokenizer<T> {
-    file: BufReader<T>,
-    buf: String,
+pub struct Tokenizer {
+    source: String,
+    /// Byte offset of first unread character
+    position: usize,
+    /// Line of `position` and byte offset of its first character
+    line: usize,
+    line_start: usize,
+    /// Location of token returned by last `advance` or `peek_token`
+    span: Span,
 }
 
-impl<T: std::io::Read> Tokenizer<T> {
-    pub fn new(file: T) -> std::io::Result<Tokenizer<T>> {
+impl Tokenizer {
+    /// Reads whole source, invalid UTF-8 is replaced and reported as unknown symbols
+    pub fn new<T: Read>(file: T) -> std::io::Result<Tokenizer> {
+        let mut bytes = Vec::with_capacity(2048);
+        BufReader::new(file).read_to_end(&mut bytes)?;
+
         Ok(Tokenizer {
-            file: BufReader::new(file),
-            buf: String::with_capacity(2048),
+            source: String::from_utf8_lossy(&bytes).into_owned(),
+            position: 0,
+            line: 1,
+            line_start: 0,
+            span: Span { line: 1, column: 1 },
         })
     }
 
-    fn get_next_token(&mut self, delete: bool) -> Option<Result<Token>> {
-        // `while let` do not make possibility to get error from read_line
+    /// Moves over next `n` bytes of source counting lines
+    fn skip(&mut self, n: usize) {
+        let end = self.position + n;
+        for (index, c) in self.source[self.position..end].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.position + index + 1;
+            }
+        }
+        self.position = end;
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
         loop {
-            if self.buf.len() == 0 {
-                let bytes = self.file.read_line(&mut self.buf);
-                let bytes = match bytes {
-                    Ok(n) => n,
-                    Err(e) => return Some(Err(e)),
-                };
-                if bytes == 0 {
-                    return None;
-                }
+            let rest = &self.source[self.position..];
+            let trimmed = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            let mut n = rest.len() - trimmed.len();
+
+            if trimmed.starts_with("//") {
+                n += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(end) = trimmed
+                .strip_prefix("/*")
+                .and_then(|comment| comment.find("*/"))
+            {
+                n += end + "/**/".len();
+            } else {
+                // Next token, or unclosed comment reported as error by `parse_token`
+                self.skip(n);
+                return;
             }
 
-            // Clearing comments
-            trim(&mut self.buf);
+            self.skip(n);
+        }
+    }
 
-            let mut line = self.buf.as_str();
+    fn get_next_token(&mut self, delete: bool) -> Option<Result<Token, SyntaxError>> {
+        self.skip_whitespace_and_comments();
 
-            if line.contains("//") {
-                let index = line.find("//").expect("checked previously");
-                self.buf.replace_range(index.., "");
-                trim(&mut self.buf);
-                line = self.buf.as_str();
-            }
+        let column = self.source[self.line_start..self.position].chars().count() + 1;
+        self.span = Span {
+            line: self.line,
+            column,
+        };
 
-            if line.contains("/*") {
-                if line.contains("*/") {
-                    let start = line.find("/*").expect("checked");
-                    let end = line.find("*/").expect("checked");
-
-                    self.buf.replace_range(start..(end + "*/".len()), "");
-                    line = self.buf.trim();
-                } else {
-                    while let Ok(bytes) = self.file.read_line(&mut self.buf) {
-                        if bytes == 0 {
-                            return None;
-                        }
-
-                        if self.buf.contains("*/") {
-                            break;
-                        }
-                    }
-
-                    let start = self.buf.find("/*").expect("checked");
-                    let end = self.buf.find("*/").expect("checked");
-
-                    self.buf.replace_range(start..(end + "*/".len()), "");
-                    line = self.buf.trim();
+        let rest = &self.source[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (n, token) = match Self::parse_token(rest) {
+            Ok(token) => token,
+            Err(LexError {
+                skip,
+                expected,
+                found,
+            }) => {
+                if delete {
+                    self.skip(skip.0);
                 }
+                return Some(Err(self.error(expected, found)));
             }
-            if line.len() == 0 {
-                continue;
-            }
-
-            let (n, token) = Self::parse_token(line);
-            if delete {
-                self.buf.replace_range(..n.0, "");
-            }
-
-            return Some(Ok(token));
+        };
+        if delete {
+            self.skip(n.0);
         }
+
+        Some(Ok(token))
     }
 
-    pub fn advance(&mut self) -> Option<Result<Token>> {
+    pub fn advance(&mut self) -> Option<Result<Token, SyntaxError>> {
         const DELETE: bool = true;
         self.get_next_token(DELETE)
     }
 
-    pub(crate) fn peek_token(&mut self) -> Option<Result<Token>> {
+    pub(crate) fn peek_token(&mut self) -> Option<Result<Token, SyntaxError>> {
         const DELETE: bool = false;
         self.get_next_token(DELETE)
     }
 
-    fn parse_token(line: &str) -> (CharsNumber, Token) {
+    /// Location of token returned by last `advance` or `peek_token`,
+    /// or of end of file if there was no token
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Error at location of last token
+    pub fn error(&self, expected: Vec<String>, found: String) -> SyntaxError {
+        SyntaxError {
+            span: self.span,
+            expected,
+            found,
+            source_line: self
+                .source
+                .lines()
+                .nth(self.span.line - 1)
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+
+    fn parse_token(line: &str) -> std::result::Result<(CharsNumber, Token), LexError> {
         let first_symbol = line.chars().nth(0).expect("already checked");
-        if first_symbol.is_alphabetic() {
-            Self::parse_keyword_or_identifier(&line)
+        if first_symbol.is_ascii_alphabetic() || first_symbol == '_' {
+            Ok(Self::parse_keyword_or_identifier(line))
         } else if first_symbol.is_ascii_digit() {
-            Self::parse_nubmer(&line)
+            Self::parse_nubmer(line)
+        } else if line.starts_with("/*") {
+            // Closed comments are skipped before
+            Err(LexError {
+                skip: CharsNumber(line.len()),
+                expected: vec![],
+                found: "`/*` without closing `*/`".to_owned(),
+            })
         } else if let Some(symbol) = SYMBOL_LIST.iter().find(|c| **c == first_symbol) {
-            (CharsNumber(1), Token::Symbol(symbol))
+            Ok((CharsNumber(1), Token::Symbol(symbol)))
         } else if first_symbol == '"' {
-            let line = &line[1..];
-            let end_string_index = line.find('"').expect("could not find closing \"");
-            let string = String::from(&line[..end_string_index]);
+            let rest = &line[1..];
+            let end = rest.find(['"', '\n', '\r']).unwrap_or(rest.len());
+            if !rest[end..].starts_with('"') {
+                return Err(LexError {
+                    skip: CharsNumber(end + 1),
+                    expected: vec![],
+                    found: "`\"` without closing `\"` on the same line".to_owned(),
+                });
+            }
+            // Hack character set has no codes for other characters
+            if let Some(c) = rest[..end].chars().find(|c| !(' '..='~').contains(c)) {
+                return Err(LexError {
+                    skip: CharsNumber(end + 2),
+                    expected: vec!["printable ASCII character in string".to_owned()],
+                    found: format!("`{}`", c.escape_default()),
+                });
+            }
+            let string = String::from(&rest[..end]);
             // +2 needed because of starting end closing " symbols
-            (CharsNumber(string.len() + 2), Token::StringConst(string))
+            Ok((CharsNumber(string.len() + 2), Token::StringConst(string)))
         } else {
-            panic!(
-                "unknown symbol occured \"{}\" in line {}",
-                first_symbol, line
-            );
+            Err(LexError {
+                skip: CharsNumber(first_symbol.len_utf8()),
+                expected: vec![],
+                found: format!("symbol `{first_symbol}`"),
+            })
         }
     }
 
     fn parse_keyword_or_identifier(line: &str) -> (CharsNumber, Token) {
         let token;
-        if let Some(index) = line.find(|c: char| !(c.is_alphabetic() || c == '_')) {
-            token = &line[..index]; // TODO: may be bug here
+        if let Some(index) = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+            token = &line[..index];
         } else {
-            token = &line;
+            token = line;
         }
 
         use Keyword::*;
@@ -205,19 +318,24 @@ impl<T: std::io::Read> Tokenizer<T> {
         }
     }
 
-    fn parse_nubmer(line: &str) -> (CharsNumber, Token) {
+    fn parse_nubmer(line: &str) -> std::result::Result<(CharsNumber, Token), LexError> {
         let number;
         if let Some(index) = line.find(|c: char| !c.is_ascii_digit()) {
             number = &line[..index];
         } else {
-            number = &line;
+            number = line;
         }
 
+        // Digits are checked already, so parsing fails only on overflow
         match number.parse::<u16>() {
-            Ok(result_number) => (CharsNumber(number.len()), Token::IntConst(result_number)),
-            Err(err) => {
-                panic!("Can not parse integer: {}", err);
+            Ok(result_number) if result_number <= MAX_INT => {
+                Ok((CharsNumber(number.len()), Token::IntConst(result_number)))
             }
+            _ => Err(LexError {
+                skip: CharsNumber(number.len()),
+                expected: vec![format!("integer constant up to {MAX_INT}")],
+                found: format!("`{number}`"),
+            }),
         }
     }
 }
@@ -320,6 +438,167 @@ mod tests {
         assert!(result);
     }
 
+    #[test]
+    fn tracks_lines_and_columns_of_tokens() {
+        let buf = "class\n\n/* multi\nline */ Main // comment\n\t{ \"a // b\"".as_bytes();
+        let mut t = Tokenizer::new(buf).unwrap();
+
+        t.advance();
+        assert_eq!(t.span(), Span { line: 1, column: 1 });
+        assert!(matches!(t.advance(), Some(Ok(Token::Identifier(name))) if name == "Main"));
+        assert_eq!(t.span(), Span { line: 4, column: 9 });
+        t.peek_token();
+        assert_eq!(t.span(), Span { line: 5, column: 2 });
+        t.advance();
+        assert!(matches!(t.advance(), Some(Ok(Token::StringConst(s))) if s == "a // b"));
+        assert_eq!(t.span(), Span { line: 5, column: 4 });
+        assert!(t.advance().is_none());
+        assert_eq!(
+            t.span(),
+            Span {
+                line: 5,
+                column: 12
+            }
+        );
+    }
+
+    /// Tokens of source up to end or first error
+    fn lex(source: &str) -> (Vec<Token>, Option<SyntaxError>) {
+        let mut t = Tokenizer::new(source.as_bytes()).unwrap();
+        let mut tokens = vec![];
+        while let Some(token) = t.advance() {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(err) => return (tokens, Some(err)),
+            }
+        }
+
+        (tokens, None)
+    }
+
+    fn identifier(name: &str) -> Token {
+        Token::Identifier(name.to_owned())
+    }
+
+    #[test]
+    fn identifiers_contain_digits_and_underscores() {
+        let (tokens, err) = lex("x1 _tmp a_2b __ 9lives classy");
+
+        assert!(err.is_none());
+        assert_eq!(
+            tokens,
+            vec![
+                identifier("x1"),
+                identifier("_tmp"),
+                identifier("a_2b"),
+                identifier("__"),
+                Token::IntConst(9),
+                identifier("lives"),
+                identifier("classy"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_non_ascii_letters() {
+        let (tokens, err) = lex("let café = 1;");
+        let err = err.unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![Token::Keyword(Keyword::Let), identifier("caf")]
+        );
+        assert_eq!(err.span, Span { line: 1, column: 8 });
+        assert_eq!(err.found, "symbol `é`");
+
+        let (tokens, err) = lex("x\u{00a0}y");
+        assert_eq!(tokens, vec![identifier("x")]);
+        assert_eq!(err.unwrap().found, "symbol `\u{00a0}`");
+    }
+
+    #[test]
+    fn limits_integer_constants() {
+        let (tokens, err) = lex("0 32767");
+        assert!(err.is_none());
+        assert_eq!(tokens, vec![Token::IntConst(0), Token::IntConst(32767)]);
+
+        for number in ["32768", "65535", "65536", "123456789012345678901"] {
+            let (tokens, err) = lex(&format!("1 {number}"));
+            let err = err.unwrap();
+
+            assert_eq!(tokens, vec![Token::IntConst(1)]);
+            assert_eq!(err.span, Span { line: 1, column: 3 });
+            assert_eq!(err.expected, vec!["integer constant up to 32767"]);
+            assert_eq!(err.found, format!("`{number}`"));
+        }
+    }
+
+    #[test]
+    fn reports_unterminated_string_and_continues_on_next_line() {
+        let mut t = Tokenizer::new("let s = \"abc;\r\nreturn \"\";".as_bytes()).unwrap();
+        for _ in 0..3 {
+            t.advance();
+        }
+
+        let err = t.advance().unwrap().unwrap_err();
+        assert_eq!(err.span, Span { line: 1, column: 9 });
+        assert_eq!(err.found, "`\"` without closing `\"` on the same line");
+        assert_eq!(err.source_line, "let s = \"abc;");
+
+        assert!(matches!(
+            t.advance(),
+            Some(Ok(Token::Keyword(Keyword::Return)))
+        ));
+        assert!(matches!(t.advance(), Some(Ok(Token::StringConst(s))) if s.is_empty()));
+        assert!(matches!(t.advance(), Some(Ok(Token::Symbol(';')))));
+
+        let (_, err) = lex("\"no end");
+        assert_eq!(err.unwrap().span, Span { line: 1, column: 1 });
+    }
+
+    #[test]
+    fn rejects_string_characters_outside_jack_character_set() {
+        for (source, found) in [
+            ("\"café\" x", "`\\u{e9}`"),
+            ("\"a\tb\" x", "`\\t`"),
+            ("\"\u{1F600}\" x", "`\\u{1f600}`"),
+        ] {
+            let mut t = Tokenizer::new(source.as_bytes()).unwrap();
+            let err = t.advance().unwrap().unwrap_err();
+
+            assert_eq!(err.span, Span { line: 1, column: 1 });
+            assert_eq!(err.expected, vec!["printable ASCII character in string"]);
+            assert_eq!(err.found, found);
+            assert_eq!(t.advance().unwrap().unwrap(), identifier("x"));
+        }
+    }
+
+    #[test]
+    fn reports_unclosed_comment() {
+        let mut t =
+            Tokenizer::new("do f(); /* comment\n * never closed */ /* here\n".as_bytes()).unwrap();
+        for _ in 0..5 {
+            t.advance();
+        }
+
+        // Error stays in place until it is consumed
+        assert!(t.peek_token().unwrap().is_err());
+        let err = t.advance().unwrap().unwrap_err();
+        assert_eq!(
+            err.span,
+            Span {
+                line: 2,
+                column: 20
+            }
+        );
+        assert_eq!(err.found, "`/*` without closing `*/`");
+        assert!(t.advance().is_none());
+
+        let (tokens, err) = lex("/*/ x");
+        assert!(tokens.is_empty());
+        assert_eq!(err.unwrap().span, Span { line: 1, column: 1 });
+    }
+
     #[test]
     fn peek_token_does_not_consume_input() {
         let buf = "class Main_class".as_bytes();
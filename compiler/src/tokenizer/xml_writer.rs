@@ -1,79 +1,126 @@
+use std::borrow::Cow;
 use std::io::{Result, Write};
 
-use super::Token;
+use super::{Token, Tokenizer};
+use crate::error::SyntaxErrors;
 
 pub trait SimpleXmlWriter {
     fn write_xml<W>(&self, writer: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        self.write_xml_with(writer, false)
+    }
+
+    /// With `compatible` text is padded by spaces as in reference files of the course,
+    /// e.g. `<keyword> class </keyword>`
+    fn write_xml_with<W>(&self, writer: &mut W, compatible: bool) -> Result<()>
     where
         W: Write;
 }
 
+/// Replaces characters, which have special meaning in XML, with entities
+pub fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['<', '>', '&', '"']) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
 impl SimpleXmlWriter for Token {
-    fn write_xml<W>(&self, writer: &mut W) -> Result<()>
+    fn write_xml_with<W>(&self, writer: &mut W, compatible: bool) -> Result<()>
     where
         W: Write,
     {
-        match self {
-            Token::Keyword(keyword) => {
-                let kw = match keyword {
-                    super::Keyword::Class => "class",
-                    super::Keyword::Method => "method",
-                    super::Keyword::Function => "function",
-                    super::Keyword::Constructor => "constructor",
-                    super::Keyword::Int => "int",
-                    super::Keyword::Boolean => "boolean",
-                    super::Keyword::Char => "char",
-                    super::Keyword::Void => "void",
-                    super::Keyword::Var => "var",
-                    super::Keyword::Static => "static",
-                    super::Keyword::Field => "field",
-                    super::Keyword::Let => "let",
-                    super::Keyword::Do => "do",
-                    super::Keyword::If => "if",
-                    super::Keyword::Else => "else",
-                    super::Keyword::While => "while",
-                    super::Keyword::Return => "return",
-                    super::Keyword::True => "true",
-                    super::Keyword::False => "false",
-                    super::Keyword::Null => "null",
-                    super::Keyword::This => "this",
-                };
-                writer.write_all(b"<keyword>")?;
-                writer.write_all(kw.as_bytes())?;
-                writer.write_all(b"</keyword>")?;
-            }
-            Token::Symbol(symbol) => {
-                // Avoiding allocation for chars
-                let mut tmp = [0u8; 4];
-                let s = match symbol {
-                    '<' => "&lt;",
-                    '>' => "&gt;",
-                    '"' => "&quot;",
-                    '&' => "&amp;",
-                    _ => symbol.encode_utf8(&mut tmp),
-                };
+        let (tag, text) = match self {
+            Token::Keyword(keyword) => ("keyword", keyword.to_string()),
+            Token::Symbol(symbol) => ("symbol", symbol.to_string()),
+            Token::Identifier(name) => ("identifier", name.clone()),
+            Token::IntConst(int) => ("integerConstant", int.to_string()),
+            // Written without quotes
+            Token::StringConst(string) => ("stringConstant", string.clone()),
+        };
+        let padding = if compatible { " " } else { "" };
 
-                writer.write_all(b"<symbol>")?;
-                writer.write_all(s.as_bytes())?;
-                writer.write_all(b"</symbol>")?;
-            }
-            Token::Identifier(text) => {
-                writer.write_all(b"<identifier>")?;
-                writer.write_all(text.as_bytes())?;
-                writer.write_all(b"</identifier>")?;
-            }
-            Token::IntConst(int) => {
-                let int = int.to_string(); // FIXME: need to avoid allocation
-                writer.write_all(b"<integerConstant>")?;
-                writer.write_all(int.as_bytes())?;
-                writer.write_all(b"</integerConstant>")?;
-            }
-            Token::StringConst(string) => {
-                writer.write_all(b"<stringConstant>")?;
-                writer.write_all(string.as_bytes())?;
-                writer.write_all(b"</stringConstant>")?;
+        write!(writer, "<{tag}>{padding}{}{padding}</{tag}>", escape(&text))
+    }
+}
+
+/// Writes every token of source inside `<tokens>` tag, one token per line.
+/// Fails with all lexical errors of source, if there are any
+pub fn write_tokens<W: Write>(
+    tokenizer: &mut Tokenizer,
+    writer: &mut W,
+    compatible: bool,
+) -> Result<()> {
+    let mut errors = vec![];
+
+    writer.write_all(b"<tokens>\n")?;
+    while let Some(token) = tokenizer.advance() {
+        match token {
+            Ok(token) => {
+                token.write_xml_with(writer, compatible)?;
+                writer.write_all(b"\n")?;
             }
+            Err(err) => errors.push(err),
         }
-        Ok(())
+    }
+    writer.write_all(b"</tokens>\n")?;
+
+    if !errors.is_empty() {
+        return Err(SyntaxErrors(errors).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str, compatible: bool) -> String {
+        let mut tokenizer = Tokenizer::new(source.as_bytes()).unwrap();
+        let mut buf = vec![];
+        write_tokens(&mut tokenizer, &mut buf, compatible).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn writes_tokens_like_reference_files() {
+        assert_eq!(
+            tokens("if (x < 1) { let s = \"a<b & \"; }", true),
+            "<tokens>
+<keyword> if </keyword>
+<symbol> ( </symbol>
+<identifier> x </identifier>
+<symbol> &lt; </symbol>
+<integerConstant> 1 </integerConstant>
+<symbol> ) </symbol>
+<symbol> { </symbol>
+<keyword> let </keyword>
+<identifier> s </identifier>
+<symbol> = </symbol>
+<stringConstant> a&lt;b &amp;  </stringConstant>
+<symbol> ; </symbol>
+<symbol> } </symbol>
+</tokens>
+"
+        );
+        assert_eq!(
+            tokens("x > \"&\"", false),
+            "<tokens>\n<identifier>x</identifier>\n<symbol>&gt;</symbol>\n<stringConstant>&amp;</stringConstant>\n</tokens>\n"
+        );
     }
 }
@@ -0,0 +1,43 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use translator::{
+    bytecode,
+    vm::{self, VmFile},
+};
+
+const USAGE: &str = "usage: vm asm <file.vm>... | vm disasm <file.vmb>";
+
+/// Compiles text `.vm` file into binary `.vmb` file next to it
+fn assemble(path: &Path) -> Result<(), io::Error> {
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let commands = vm::parse_program(&fs::read_to_string(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{name}:{err}")))?;
+
+    let file = BufWriter::new(File::create(path.with_extension("vmb"))?);
+    bytecode::write_module(&VmFile::new(name, commands), file)
+}
+
+/// Prints binary module as `.vm` text
+fn disassemble(path: &Path) -> Result<(), io::Error> {
+    let file = bytecode::read_module(BufReader::new(File::open(path)?))?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    vm::write_program(&file.commands, &mut stdout)
+}
+
+fn main() -> Result<(), io::Error> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.split_first() {
+        Some((command, paths)) if command == "asm" && !paths.is_empty() => {
+            paths.iter().try_for_each(|path| assemble(path.as_ref()))
+        }
+        Some((command, [path])) if command == "disasm" => disassemble(path.as_ref()),
+        _ => Err(io::Error::other(USAGE)),
+    }
+}
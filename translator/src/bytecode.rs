@@ -0,0 +1,298 @@
+//! Binary encoding of VM modules (`.vmb` files).
+//!
+//! All numbers are little endian. A module is:
+//! * magic `HVMB` and format version byte
+//! * string table: `u16` count, then every string as `u16` byte length and UTF-8 bytes.
+//!   Index 0 is the module name, other strings are function and label names
+//! * `u32` number of commands, then commands as opcode byte and operands:
+//!   segments and arithmetic operations are single bytes, indices and counts are `u16`,
+//!   names are `u16` indices into string table
+//!
+//! ```
+//! use translator::{bytecode, vm::{parse_program, VmFile}};
+//!
+//! let file = VmFile::new("Main.vm", parse_program("function Main.main 0\npush constant 1\nreturn").unwrap());
+//! let mut buf = vec![];
+//! bytecode::write_module(&file, &mut buf).unwrap();
+//!
+//! assert_eq!(bytecode::read_module(buf.as_slice()).unwrap(), file);
+//! ```
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use crate::vm::{ArtithmeticOperation, Segment, VmCommand, VmFile};
+
+const MAGIC: &[u8; 4] = b"HVMB";
+const VERSION: u8 = 1;
+
+const ARITHMETIC: u8 = 0;
+const PUSH: u8 = 1;
+const POP: u8 = 2;
+const LABEL: u8 = 3;
+const GOTO: u8 = 4;
+const IF: u8 = 5;
+const FUNCTION: u8 = 6;
+const RETURN: u8 = 7;
+const CALL: u8 = 8;
+
+const OPERATIONS: [ArtithmeticOperation; 12] = {
+    use ArtithmeticOperation::*;
+    [Add, Sub, Neg, Eq, Gt, Lt, And, Or, Not, Mul, Div, Mod]
+};
+
+const SEGMENTS: [Segment; 8] = {
+    use Segment::*;
+    [Argument, Local, Static, Constant, This, That, Pointer, Temp]
+};
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Collects strings in order of first use
+#[derive(Default)]
+struct StringTable<'a> {
+    strings: Vec<&'a str>,
+    indices: HashMap<&'a str, u16>,
+}
+
+impl<'a> StringTable<'a> {
+    fn index(&mut self, string: &'a str) -> io::Result<u16> {
+        if let Some(index) = self.indices.get(string) {
+            return Ok(*index);
+        }
+
+        let index = u16::try_from(self.strings.len())
+            .map_err(|_| invalid("module has more than 65535 names"))?;
+        self.strings.push(string);
+        self.indices.insert(string, index);
+
+        Ok(index)
+    }
+}
+
+fn name(command: &VmCommand) -> Option<&str> {
+    use VmCommand::*;
+
+    match command {
+        Label(name) | Goto(name) | If(name) => Some(name),
+        Function { name, .. } | Call { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+/// Writes VM file as binary module. Source lines are not stored
+pub fn write_module<W: Write>(file: &VmFile, mut writer: W) -> io::Result<()> {
+    use VmCommand::*;
+
+    let mut table = StringTable::default();
+    table.index(&file.name)?;
+    for command in &file.commands {
+        if let Some(name) = name(command) {
+            table.index(name)?;
+        }
+    }
+
+    let mut buf = Vec::with_capacity(file.commands.len() * 4);
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+
+    buf.extend_from_slice(&(table.strings.len() as u16).to_le_bytes());
+    for string in &table.strings {
+        let length = u16::try_from(string.len()).map_err(|_| invalid("name is too long"))?;
+        buf.extend_from_slice(&length.to_le_bytes());
+        buf.extend_from_slice(string.as_bytes());
+    }
+
+    let count = u32::try_from(file.commands.len()).map_err(|_| invalid("module is too big"))?;
+    buf.extend_from_slice(&count.to_le_bytes());
+
+    let segment = |segment: &Segment| SEGMENTS.iter().position(|s| s == segment).unwrap() as u8;
+    for command in &file.commands {
+        let name = name(command).map(|name| table.indices[name]);
+
+        match command {
+            Arithmetic(op) => {
+                buf.push(ARITHMETIC);
+                buf.push(OPERATIONS.iter().position(|o| o == op).unwrap() as u8);
+            }
+            Push { segment: s, index } | Pop { segment: s, index } => {
+                buf.push(if let Push { .. } = command { PUSH } else { POP });
+                buf.push(segment(s));
+                buf.extend_from_slice(&index.to_le_bytes());
+            }
+            Label(_) | Goto(_) | If(_) => {
+                buf.push(match command {
+                    Label(_) => LABEL,
+                    Goto(_) => GOTO,
+                    _ => IF,
+                });
+                buf.extend_from_slice(&name.unwrap().to_le_bytes());
+            }
+            Function { locals: count, .. } | Call { args: count, .. } => {
+                buf.push(if let Function { .. } = command {
+                    FUNCTION
+                } else {
+                    CALL
+                });
+                buf.extend_from_slice(&name.unwrap().to_le_bytes());
+                buf.extend_from_slice(&count.to_le_bytes());
+            }
+            Return => buf.push(RETURN),
+        }
+    }
+
+    writer.write_all(&buf)
+}
+
+/// Reads bytes of module one field at a time
+struct Reader {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl Reader {
+    fn take(&mut self, count: usize) -> io::Result<&[u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid("unexpected end of module"))?;
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn segment(&mut self) -> io::Result<Segment> {
+        let byte = self.u8()?;
+        SEGMENTS
+            .get(byte as usize)
+            .copied()
+            .ok_or_else(|| invalid(format!("unknown segment {byte}")))
+    }
+}
+
+/// Reads binary module written by `write_module`
+pub fn read_module<R: Read>(mut reader: R) -> io::Result<VmFile> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let mut reader = Reader { bytes, position: 0 };
+
+    if reader.take(4)? != MAGIC {
+        return Err(invalid("not a VM bytecode module"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported bytecode version {version}")));
+    }
+
+    let mut strings = vec![];
+    for _ in 0..reader.u16()? {
+        let length = reader.u16()? as usize;
+        let string = String::from_utf8(reader.take(length)?.to_vec())
+            .map_err(|_| invalid("name is not valid UTF-8"))?;
+        strings.push(string);
+    }
+    let name = strings
+        .first()
+        .cloned()
+        .ok_or_else(|| invalid("module has no name"))?;
+    let string = |reader: &mut Reader| -> io::Result<String> {
+        let index = reader.u16()?;
+        strings
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| invalid(format!("string index {index} is out of range")))
+    };
+
+    let mut file = VmFile::new(name, vec![]);
+
+    for _ in 0..reader.u32()? {
+        let command = match reader.u8()? {
+            ARITHMETIC => {
+                let byte = reader.u8()?;
+                VmCommand::Arithmetic(
+                    *OPERATIONS
+                        .get(byte as usize)
+                        .ok_or_else(|| invalid(format!("unknown operation {byte}")))?,
+                )
+            }
+            PUSH => VmCommand::push(reader.segment()?, reader.u16()?),
+            POP => VmCommand::pop(reader.segment()?, reader.u16()?),
+            LABEL => VmCommand::Label(string(&mut reader)?),
+            GOTO => VmCommand::Goto(string(&mut reader)?),
+            IF => VmCommand::If(string(&mut reader)?),
+            FUNCTION => VmCommand::function(string(&mut reader)?, reader.u16()?),
+            CALL => VmCommand::call(string(&mut reader)?, reader.u16()?),
+            RETURN => VmCommand::Return,
+            opcode => return Err(invalid(format!("unknown opcode {opcode}"))),
+        };
+        file.commands.push(command);
+    }
+
+    if reader.position != reader.bytes.len() {
+        return Err(invalid("unexpected data after last command"));
+    }
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn module(source: &str) -> VmFile {
+        VmFile::new("Main.vm", parse_program(source).unwrap())
+    }
+
+    #[test]
+    fn round_trips_every_command() {
+        let file = module(
+            "function Main.main 2\npush constant 7\npop local 1\nlabel LOOP\nmul\nif-goto LOOP\ngoto END\ncall Main.main 0\nlabel END\nreturn",
+        );
+
+        let mut buf = vec![];
+        write_module(&file, &mut buf).unwrap();
+
+        assert_eq!(read_module(buf.as_slice()).unwrap(), file);
+    }
+
+    #[test]
+    fn names_are_stored_once() {
+        let mut buf = vec![];
+        write_module(
+            &module("label LOOP\ngoto LOOP\nif-goto LOOP\nlabel LOOP"),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(buf.windows(4).filter(|w| w == b"LOOP").count(), 1);
+    }
+
+    #[test]
+    fn rejects_damaged_modules() {
+        let mut buf = vec![];
+        write_module(&module("push constant 1\ncall Main.f 1"), &mut buf).unwrap();
+
+        let error = read_module(&buf[..buf.len() - 1]).unwrap_err();
+        assert_eq!(error.to_string(), "unexpected end of module");
+
+        assert!(read_module(b"HVMA\x01".as_slice()).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{
+    json,
+    verifier::function_bodies,
+    vm::{VmCommand, VmFile},
+};
+
+/// Function defined in program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionNode {
+    pub name: String,
+    pub file: String,
+    pub locals: u16,
+}
+
+/// All calls from one function to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    /// Source line of every call site, unknown lines are skipped
+    pub lines: Vec<usize>,
+    /// Number of call sites
+    pub count: usize,
+}
+
+/// Functions connected by `call` commands
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    /// Defined functions in program order
+    pub functions: Vec<FunctionNode>,
+    /// Edges in order of first call
+    pub calls: Vec<CallEdge>,
+    /// Groups of mutually recursive functions, including functions calling themselves
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl CallGraph {
+    pub fn new(files: &[VmFile]) -> Self {
+        let mut graph = Self::default();
+        let mut edges: HashMap<(String, String), usize> = HashMap::new();
+
+        for file in files {
+            let mut offset = 0;
+
+            for (name, body) in function_bodies(&file.commands) {
+                if let Some(VmCommand::Function { name, locals }) = body.first() {
+                    graph.functions.push(FunctionNode {
+                        name: name.clone(),
+                        file: file.name.clone(),
+                        locals: *locals,
+                    });
+                }
+
+                // Code outside of functions is only run by tests without bootstrap
+                let Some(caller) = name else {
+                    offset += body.len();
+                    continue;
+                };
+
+                for (index, command) in body.iter().enumerate() {
+                    if let VmCommand::Call { name: callee, .. } = command {
+                        let key = (caller.to_owned(), callee.clone());
+                        let edge = *edges.entry(key).or_insert_with(|| {
+                            graph.calls.push(CallEdge {
+                                caller: caller.to_owned(),
+                                callee: callee.clone(),
+                                lines: vec![],
+                                count: 0,
+                            });
+                            graph.calls.len() - 1
+                        });
+
+                        graph.calls[edge].count += 1;
+                        graph.calls[edge].lines.extend(file.line(offset + index));
+                    }
+                }
+
+                offset += body.len();
+            }
+        }
+
+        graph.cycles = graph.find_cycles();
+        graph
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.functions.iter().any(|function| function.name == name)
+    }
+
+    /// Strongly connected components (Tarjan's algorithm) which contain a cycle
+    fn find_cycles(&self) -> Vec<Vec<String>> {
+        struct State<'a> {
+            successors: HashMap<&'a str, Vec<&'a str>>,
+            index: HashMap<&'a str, usize>,
+            low: HashMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            components: Vec<Vec<&'a str>>,
+        }
+
+        fn visit<'a>(state: &mut State<'a>, node: &'a str) {
+            let index = state.index.len();
+            state.index.insert(node, index);
+            state.low.insert(node, index);
+            state.stack.push(node);
+
+            let successors = state.successors.get(node).cloned().unwrap_or_default();
+            for next in successors {
+                if !state.index.contains_key(next) {
+                    visit(state, next);
+                    let low = state.low[node].min(state.low[next]);
+                    state.low.insert(node, low);
+                } else if state.stack.contains(&next) {
+                    let low = state.low[node].min(state.index[next]);
+                    state.low.insert(node, low);
+                }
+            }
+
+            if state.low[node] == state.index[node] {
+                let position = state.stack.iter().rposition(|n| *n == node).unwrap();
+                state.components.push(state.stack.split_off(position));
+            }
+        }
+
+        let mut state = State {
+            successors: HashMap::new(),
+            index: HashMap::new(),
+            low: HashMap::new(),
+            stack: vec![],
+            components: vec![],
+        };
+        for call in &self.calls {
+            state
+                .successors
+                .entry(call.caller.as_str())
+                .or_default()
+                .push(call.callee.as_str());
+        }
+
+        for function in &self.functions {
+            if !state.index.contains_key(function.name.as_str()) {
+                visit(&mut state, &function.name);
+            }
+        }
+
+        let position = |name: &str| self.functions.iter().position(|f| f.name == name);
+        let mut cycles: Vec<Vec<String>> = state
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || self
+                        .calls
+                        .iter()
+                        .any(|call| call.caller == component[0] && call.callee == component[0])
+            })
+            .map(|mut component| {
+                component.sort_by_key(|name| position(name));
+                component.into_iter().map(String::from).collect()
+            })
+            .collect();
+        cycles.sort_by_key(|cycle| position(&cycle[0]));
+
+        cycles
+    }
+
+    fn is_recursive(&self, name: &str) -> bool {
+        self.cycles
+            .iter()
+            .flatten()
+            .any(|function| function == name)
+    }
+
+    /// Writes graph in Graphviz DOT format. Recursive functions and calls inside
+    /// cycles are red, calls to undefined functions are dashed
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "digraph calls {{")?;
+        writeln!(writer, "    node [shape=box];")?;
+
+        for function in &self.functions {
+            let color = if self.is_recursive(&function.name) {
+                ", color=red"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "    {} [label={}{color}];",
+                json::string(&function.name),
+                json::string(&format!("{}\n{} locals", function.name, function.locals)),
+            )?;
+        }
+
+        for call in &self.calls {
+            let mut attributes = vec![format!("label=\"{}\"", call.count)];
+            if !self.is_defined(&call.callee) {
+                attributes.push("style=dashed".to_owned());
+            }
+            if self
+                .cycles
+                .iter()
+                .any(|cycle| cycle.contains(&call.caller) && cycle.contains(&call.callee))
+            {
+                attributes.push("color=red".to_owned());
+            }
+
+            writeln!(
+                writer,
+                "    {} -> {} [{}];",
+                json::string(&call.caller),
+                json::string(&call.callee),
+                attributes.join(", ")
+            )?;
+        }
+
+        writeln!(writer, "}}")
+    }
+
+    /// Writes graph as JSON object with `functions`, `calls` and `cycles` arrays
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|function| {
+                format!(
+                    "    {{\"name\": {}, \"file\": {}, \"locals\": {}, \"recursive\": {}}}",
+                    json::string(&function.name),
+                    json::string(&function.file),
+                    function.locals,
+                    self.is_recursive(&function.name)
+                )
+            })
+            .collect();
+
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|call| {
+                let lines: Vec<String> = call.lines.iter().map(|line| line.to_string()).collect();
+                format!(
+                    "    {{\"caller\": {}, \"callee\": {}, \"count\": {}, \"lines\": [{}], \"defined\": {}}}",
+                    json::string(&call.caller),
+                    json::string(&call.callee),
+                    call.count,
+                    lines.join(", "),
+                    self.is_defined(&call.callee)
+                )
+            })
+            .collect();
+
+        let cycles: Vec<String> = self
+            .cycles
+            .iter()
+            .map(|cycle| {
+                let names: Vec<String> = cycle.iter().map(|name| json::string(name)).collect();
+                format!("    [{}]", names.join(", "))
+            })
+            .collect();
+
+        writeln!(writer, "{{")?;
+        writeln!(
+            writer,
+            "  \"functions\": [\n{}\n  ],",
+            functions.join(",\n")
+        )?;
+        writeln!(writer, "  \"calls\": [\n{}\n  ],", calls.join(",\n"))?;
+        writeln!(writer, "  \"cycles\": [\n{}\n  ]", cycles.join(",\n"))?;
+        writeln!(writer, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn graph() -> CallGraph {
+        let main = parse_program(
+            "function Main.main 2\ncall Main.even 1\ncall Main.even 1\ncall Math.abs 1\nreturn\nfunction Main.even 0\ncall Main.odd 1\nreturn\nfunction Main.odd 0\ncall Main.even 1\nreturn\nfunction Main.loop 0\ncall Main.loop 0\nreturn",
+        )
+        .unwrap();
+        let mut file = VmFile::new("Main.vm", main);
+        file.lines = (1..=file.commands.len()).collect();
+
+        CallGraph::new(&[file])
+    }
+
+    #[test]
+    fn counts_call_sites() {
+        let graph = graph();
+        let call = &graph.calls[0];
+
+        assert_eq!(
+            (call.caller.as_str(), call.callee.as_str()),
+            ("Main.main", "Main.even")
+        );
+        assert_eq!(call.count, 2);
+        assert_eq!(call.lines, vec![2, 3]);
+        assert_eq!(graph.functions[0].locals, 2);
+    }
+
+    #[test]
+    fn finds_recursion_cycles() {
+        assert_eq!(
+            graph().cycles,
+            vec![vec!["Main.even", "Main.odd"], vec!["Main.loop"]]
+        );
+    }
+
+    #[test]
+    fn writes_dot() {
+        let mut buf = vec![];
+        graph().write_dot(&mut buf).unwrap();
+        let dot = String::from_utf8(buf).unwrap();
+
+        assert!(dot.contains("\"Main.main\" -> \"Main.even\" [label=\"2\"];"));
+        assert!(dot.contains("\"Main.main\" -> \"Math.abs\" [label=\"1\", style=dashed];"));
+        assert!(dot.contains("\"Main.odd\" -> \"Main.even\" [label=\"1\", color=red];"));
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::HashMap,
+    io::{Result, Write},
+};
+
+use crate::vm::{ArtithmeticOperation, Segment, VmCommand, VmFile};
+
+use super::CodeGenerator;
+
+/// First RAM address of `static` segment
+const STATIC_BASE: u16 = 16;
+
+/// Generates single portable C file running the program natively.
+///
+/// VM memory is a `uint16_t ram[32768]` with the Hack memory map, so frames,
+/// statics and heap live at the same addresses as on the Hack computer.
+/// Whole program is one `main` function: VM labels and functions are C labels,
+/// return addresses are call site numbers resolved by a `switch` at the end.
+///
+/// `call Sys.halt` and a `label X` immediately followed by `goto X` stop the
+/// program. Other endless loops, e.g. Jack `while (true) {}`, stop after
+/// `STEP_LIMIT` jumps and calls if the C file is compiled with `-DSTEP_LIMIT=n`,
+/// and the program then exits with status 2. Values of RAM addresses passed as
+/// command line arguments are printed on halt, one per line, so tests can check results.
+#[derive(Debug, Default)]
+pub struct CGenerator {
+    /// Class (file name without extension) being translated, used for `static` segment symbols
+    module: String,
+    /// Function being translated, used to scope labels
+    function: String,
+    /// Number of call sites generated so far, used as return addresses
+    call_count: u16,
+    /// RAM addresses given to static variables
+    statics: HashMap<String, u16>,
+    /// Label defined by previous command, to detect halting loops
+    previous_label: Option<String>,
+}
+
+impl CGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns any VM name into C identifier, keeping different names different
+    fn mangle(prefix: &str, name: &str) -> String {
+        let mut result = String::from(prefix);
+        for c in name.chars() {
+            match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' => result.push(c),
+                '_' => result.push_str("__"),
+                _ => result.push_str(&format!("_{:02x}", c as u32)),
+            }
+        }
+
+        result
+    }
+
+    fn function_label(name: &str) -> String {
+        Self::mangle("f_", name)
+    }
+
+    /// Label scoped by current function, or by module outside of any function
+    fn scoped_label(&self, label: &str) -> String {
+        let scope = if self.function.is_empty() {
+            &self.module
+        } else {
+            &self.function
+        };
+
+        Self::mangle("l_", &format!("{scope}${label}"))
+    }
+
+    fn static_address(&mut self, index: u16) -> u16 {
+        let next = STATIC_BASE + self.statics.len() as u16;
+
+        *self
+            .statics
+            .entry(format!("{}.{}", self.module, index))
+            .or_insert(next)
+    }
+
+    /// C expression for RAM address of `segment index`
+    fn address(&mut self, segment: &Segment, index: u16) -> String {
+        use Segment::*;
+
+        match segment {
+            Local => format!("(uint16_t)(LCL + {index})"),
+            Argument => format!("(uint16_t)(ARG + {index})"),
+            This => format!("(uint16_t)(THIS + {index})"),
+            That => format!("(uint16_t)(THAT + {index})"),
+            Pointer => (3 + index).to_string(),
+            Temp => (5 + index).to_string(),
+            Static => self.static_address(index).to_string(),
+            Constant => unreachable!("constant segment has no address"),
+        }
+    }
+
+    /// C expression for value of `segment index`
+    fn value(&mut self, segment: &Segment, index: u16) -> String {
+        match segment {
+            Segment::Constant => index.to_string(),
+            _ => format!("ram[{} & 0x7FFF]", self.address(segment, index)),
+        }
+    }
+
+    fn write_arithmetic<W: Write>(f: &mut W, op: &ArtithmeticOperation) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        let binary = match op {
+            Neg => return writeln!(f, "    TOP = -TOP;"),
+            Not => return writeln!(f, "    TOP = ~TOP;"),
+            Add => "b + TOP",
+            Sub => "TOP - b",
+            And => "TOP & b",
+            Or => "TOP | b",
+            Eq => "TOP == b ? 0xFFFF : 0",
+            Gt => "(int16_t)TOP > (int16_t)b ? 0xFFFF : 0",
+            Lt => "(int16_t)TOP < (int16_t)b ? 0xFFFF : 0",
+            Mul => "(uint16_t)((uint32_t)TOP * b)",
+            Div => "b == 0 ? 0 : (int16_t)TOP / (int16_t)b",
+            Mod => "b == 0 ? TOP : (int16_t)TOP % (int16_t)b",
+        };
+
+        writeln!(f, "    b = POP(); TOP = {binary};")
+    }
+
+    fn write_call<W: Write>(&mut self, f: &mut W, name: &str, args: u16) -> Result<()> {
+        if name == "Sys.halt" {
+            return writeln!(f, "    goto halt;");
+        }
+
+        let id = self.call_count;
+        self.call_count += 1;
+
+        writeln!(
+            f,
+            "    PUSH({id}); PUSH(LCL); PUSH(ARG); PUSH(THIS); PUSH(THAT);"
+        )?;
+        writeln!(f, "    ARG = SP - {args} - 5; LCL = SP;")?;
+        writeln!(f, "    STEP(); goto {};", Self::function_label(name))?;
+        writeln!(f, "r_{id}:;")
+    }
+}
+
+impl CodeGenerator for CGenerator {
+    fn bootstrap<W: Write>(&mut self, f: &mut W) -> Result<()> {
+        writeln!(f, "#include <stdint.h>")?;
+        writeln!(f, "#include <stdio.h>")?;
+        writeln!(f, "#include <stdlib.h>")?;
+        writeln!(f)?;
+        // Most labels are only reachable through VM jumps which may never happen
+        writeln!(f, "#ifdef __GNUC__")?;
+        writeln!(f, "#pragma GCC diagnostic ignored \"-Wunused-label\"")?;
+        writeln!(f, "#endif")?;
+        writeln!(f)?;
+        writeln!(f, "static uint16_t ram[32768];")?;
+        writeln!(f)?;
+        writeln!(f, "#define SP ram[0]")?;
+        writeln!(f, "#define LCL ram[1]")?;
+        writeln!(f, "#define ARG ram[2]")?;
+        writeln!(f, "#define THIS ram[3]")?;
+        writeln!(f, "#define THAT ram[4]")?;
+        writeln!(f, "#define TOP ram[(uint16_t)(SP - 1)]")?;
+        writeln!(f, "#define PUSH(v) (ram[SP++] = (uint16_t)(v))")?;
+        writeln!(f, "#define POP() (ram[--SP])")?;
+        writeln!(f, "#ifdef STEP_LIMIT")?;
+        writeln!(
+            f,
+            "#define STEP() if (++steps > STEP_LIMIT) {{ status = 2; goto halt; }}"
+        )?;
+        writeln!(f, "#else")?;
+        writeln!(f, "#define STEP() (void)steps")?;
+        writeln!(f, "#endif")?;
+        writeln!(f)?;
+        writeln!(f, "int main(int argc, char **argv) {{")?;
+        writeln!(f, "    uint16_t b = 0, frame = 0, ret = 0;")?;
+        writeln!(f, "    unsigned long steps = 0;")?;
+        writeln!(f, "    int status = 0;")?;
+        writeln!(f, "    (void)b;")?;
+        writeln!(f, "    (void)frame;")?;
+        writeln!(f, "    SP = 256;")?;
+
+        self.function = "Bootstrap".to_owned();
+        self.write_call(f, "Sys.init", 0)?;
+        writeln!(f, "    goto halt;")
+    }
+
+    fn start_file<W: Write>(&mut self, file: &VmFile, writer: &mut W) -> Result<()> {
+        self.module = file.module().to_owned();
+        self.function.clear();
+        self.previous_label = None;
+
+        writeln!(writer, "    /* File: {} */", file.name)
+    }
+
+    fn write_command<W: Write>(&mut self, command: &VmCommand, f: &mut W) -> Result<()> {
+        use VmCommand::*;
+
+        let previous_label = self.previous_label.take();
+
+        match command {
+            Push { segment, index } => {
+                let value = self.value(segment, *index);
+                writeln!(f, "    PUSH({value});")?;
+            }
+            Pop { segment, index } => {
+                let address = self.address(segment, *index);
+                writeln!(f, "    b = POP(); ram[{address} & 0x7FFF] = b;")?;
+            }
+            Arithmetic(op) => Self::write_arithmetic(f, op)?,
+            Label(label) => {
+                writeln!(f, "{}:;", self.scoped_label(label))?;
+                self.previous_label = Some(label.clone());
+            }
+            Goto(label) if previous_label.as_ref() == Some(label) => {
+                writeln!(f, "    goto halt;")?;
+            }
+            Goto(label) => writeln!(f, "    STEP(); goto {};", self.scoped_label(label))?,
+            If(label) => writeln!(
+                f,
+                "    if (POP() != 0) {{ STEP(); goto {}; }}",
+                self.scoped_label(label)
+            )?,
+            Function { name, locals } => {
+                self.function = name.clone();
+
+                writeln!(f, "{}:;", Self::function_label(name))?;
+                (0..*locals).try_for_each(|_| writeln!(f, "    PUSH(0);"))?;
+            }
+            Call { name, args } => self.write_call(f, name, *args)?,
+            Return => {
+                writeln!(f, "    frame = LCL; ret = ram[(uint16_t)(frame - 5)];")?;
+                writeln!(f, "    ram[ARG] = POP(); SP = ARG + 1;")?;
+                writeln!(
+                    f,
+                    "    THAT = ram[(uint16_t)(frame - 1)]; THIS = ram[(uint16_t)(frame - 2)];"
+                )?;
+                writeln!(
+                    f,
+                    "    ARG = ram[(uint16_t)(frame - 3)]; LCL = ram[(uint16_t)(frame - 4)];"
+                )?;
+                writeln!(f, "    goto dispatch;")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn comment<W: Write>(&mut self, text: &str, writer: &mut W) -> Result<()> {
+        writeln!(writer, "    /* {} */", text.replace("*/", "* /"))
+    }
+
+    fn finish<W: Write>(&mut self, f: &mut W) -> Result<()> {
+        writeln!(f, "dispatch:")?;
+        writeln!(f, "    switch (ret) {{")?;
+        (0..self.call_count).try_for_each(|id| writeln!(f, "    case {id}: goto r_{id};"))?;
+        writeln!(f, "    default:")?;
+        writeln!(
+            f,
+            "        fprintf(stderr, \"invalid return address %u\\n\", ret);"
+        )?;
+        writeln!(f, "        return 1;")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "halt:")?;
+        writeln!(f, "    for (int i = 1; i < argc; i++) {{")?;
+        writeln!(
+            f,
+            "        printf(\"%d\\n\", (int16_t)ram[atoi(argv[i]) & 0x7FFF]);"
+        )?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "    return status;")?;
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn generate(program: &str) -> String {
+        let mut generator = CGenerator::new();
+        let mut buf = vec![];
+        generator.bootstrap(&mut buf).unwrap();
+        generator
+            .start_file(&VmFile::new("Main.vm", vec![]), &mut buf)
+            .unwrap();
+        for command in parse_program(program).unwrap() {
+            generator.write_command(&command, &mut buf).unwrap();
+        }
+        generator.finish(&mut buf).unwrap();
+
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn mangles_names_into_distinct_identifiers() {
+        assert_eq!(CGenerator::mangle("f_", "Main.main"), "f_Main_2emain");
+        assert_ne!(
+            CGenerator::mangle("l_", "a_2e"),
+            CGenerator::mangle("l_", "a.")
+        );
+    }
+
+    #[test]
+    fn every_call_site_is_dispatched() {
+        let c = generate(
+            "function Sys.init 0\ncall Main.f 0\ncall Main.f 0\nfunction Main.f 0\nreturn",
+        );
+
+        assert!(c.contains("case 0: goto r_0;"));
+        assert!(c.contains("case 2: goto r_2;"));
+        assert!(c.contains("r_2:;"));
+    }
+
+    #[test]
+    fn statics_are_allocated_from_16() {
+        let c = generate("push static 3\npop static 0\npush static 3");
+
+        assert_eq!(c.matches("ram[16 & 0x7FFF]").count(), 2);
+        assert!(c.contains("ram[17 & 0x7FFF] = b;"));
+    }
+
+    /// Compiles generated C with system `cc` in temporary directory `name` and
+    /// runs it, printing `addresses`.
+    /// Returns exit status and output, or `None` if there is no C compiler
+    fn run(name: &str, c: &str, flags: &[&str], addresses: &[u16]) -> Option<(i32, Vec<i16>)> {
+        let dir = std::env::temp_dir().join(format!("translator-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.c");
+        let binary = dir.join("main");
+        fs::write(&source, c).unwrap();
+
+        let compiled = Command::new("cc")
+            .args(flags)
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .status();
+        let Ok(compiled) = compiled else {
+            fs::remove_dir_all(&dir).unwrap();
+            return None;
+        };
+        assert!(compiled.success(), "generated C does not compile");
+
+        let output = Command::new(&binary)
+            .args(addresses.iter().map(|address| address.to_string()))
+            .output()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let values = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+
+        Some((output.status.code().unwrap(), values))
+    }
+
+    #[test]
+    fn sys_halt_stops_compiled_program() {
+        let c = generate(
+            "function Sys.init 0
+push constant 7
+push constant 6
+call Main.mul 2
+pop static 0
+call Sys.halt 0
+\
+             function Main.mul 0
+push argument 0
+push argument 1
+mul
+return
+\
+             function Sys.halt 0
+label WHILE
+push constant 0
+not
+if-goto WHILE
+return",
+        );
+
+        if let Some((status, values)) = run("halt", &c, &[], &[0, 16]) {
+            assert_eq!(status, 0);
+            assert_eq!(values, vec![256 + 5, 42]);
+        }
+    }
+
+    #[test]
+    fn step_limit_stops_endless_loop() {
+        let c = generate(
+            "function Sys.init 0
+push constant 1
+pop static 0
+label WHILE
+push constant 0
+not
+if-goto WHILE
+return",
+        );
+
+        if let Some((status, values)) = run("step-limit", &c, &["-DSTEP_LIMIT=1000"], &[16]) {
+            assert_eq!(status, 2);
+            assert_eq!(values, vec![1]);
+        }
+    }
+
+    #[test]
+    fn tight_loop_halts() {
+        let c = generate("function Sys.halt 0\nlabel LOOP\ngoto LOOP");
+
+        assert!(c.contains("l_Sys_2ehalt_24LOOP:;\n    goto halt;"));
+    }
+}
@@ -0,0 +1,1072 @@
+use std::io::{Result, Write};
+
+use crate::{
+    optimizer::Instruction,
+    vm::{ArtithmeticOperation, Segment, VmCommand, VmFile},
+};
+
+use super::CodeGenerator;
+
+/// Generates Hack assembly
+#[derive(Debug, Default)]
+pub struct HackGenerator {
+    /// Class (file name without extension) being translated, used for `static` segment symbols
+    module: String,
+    /// Function being translated, used to scope labels
+    function: String,
+    /// Number of calls already made from current function
+    call_count: u16,
+    /// Number of labels generated for comparisons and routine calls,
+    /// which use reserved `$$L` prefix so they never clash with VM labels
+    label_count: u16,
+    /// Jump into shared routines (see `write_shared_routines`) instead of
+    /// expanding `call`, `return` and comparisons inline
+    compact: bool,
+    /// `mul` was used, so `$$MUL` routine must be emitted
+    uses_mul: bool,
+    /// `div` or `mod` was used, so `$$DIVMOD` routine must be emitted
+    uses_divmod: bool,
+}
+
+impl HackGenerator {
+    pub fn new(compact: bool) -> Self {
+        Self {
+            compact,
+            ..Default::default()
+        }
+    }
+
+    /// Current function, or module for commands outside of any function
+    fn scope(&self) -> &str {
+        if self.function.is_empty() {
+            &self.module
+        } else {
+            &self.function
+        }
+    }
+
+    /// Label visible only inside current function (`function$label`)
+    fn scoped_label(&self, label: &str) -> String {
+        format!("{}${}", self.scope(), label)
+    }
+
+    /// Routines shared by every `call`, `return`, `eq`, `gt` and `lt` in compact mode.
+    /// Must be emitted once per program.
+    ///
+    /// Parameters are passed in registers:
+    /// * `$$CALL` - R13 holds number of arguments, R14 holds function address
+    ///   and D holds return address
+    /// * `$$RETURN` - no parameters
+    /// * `$$EQ`, `$$GT`, `$$LT` - R15 holds return address
+    fn write_shared_routines<W: Write>(f: &mut W) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        writeln!(f, "// Shared routines")?;
+
+        writeln!(f, "($$CALL)")?;
+        Self::push_from_d_reg(f)?;
+        Self::register_to_stack(f, "LCL")?;
+        Self::register_to_stack(f, "ARG")?;
+        Self::register_to_stack(f, "THIS")?;
+        Self::register_to_stack(f, "THAT")?;
+
+        // ARG = SP - n - 5 (n - number of arguments to function)
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@5")?;
+        writeln!(f, "D=D+A")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@ARG")?;
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@SP")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@LCL")?;
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@R14")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "0;JMP")?;
+
+        writeln!(f, "($$RETURN)")?;
+        Self::write_return(f)?;
+
+        [(Eq, "$$EQ"), (Gt, "$$GT"), (Lt, "$$LT")]
+            .iter()
+            .try_for_each(|(op, name)| {
+                writeln!(f, "({name})")?;
+                Self::pop_before_op(f)?;
+                Self::compare_and_write(op, &format!("{name}.TRUE"), &format!("{name}.END"), f)?;
+                writeln!(f, "@R15")?;
+                writeln!(f, "A=M")?;
+                writeln!(f, "0;JMP")?;
+
+                Ok::<(), std::io::Error>(())
+            })?;
+
+        Ok(())
+    }
+
+    /// Routine for `mul`, R15 holds return address.
+    ///
+    /// Adds `x` shifted left for every set bit of `y`, lowest bit first,
+    /// and stops as soon as no set bits are left in `y`
+    fn write_mul_routine<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "// Multiplication routine")?;
+        writeln!(f, "($$MUL)")?;
+        // R14 = y
+        Self::pop_into_d_reg(f)?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "M=D")?;
+        // Return address is kept in freed stack slot, R15 becomes bit mask
+        writeln!(f, "@R15")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "M=D")?;
+        // R13 = x, result is accumulated in place of x
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "M=0")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "@R15")?;
+        writeln!(f, "M=1")?;
+
+        writeln!(f, "($$MUL.LOOP)")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@$$MUL.END")?;
+        writeln!(f, "D;JEQ")?;
+        writeln!(f, "@R15")?;
+        writeln!(f, "D=D&M")?;
+        writeln!(f, "@$$MUL.NEXT")?;
+        writeln!(f, "D;JEQ")?;
+        // Bit is set: clear it in y and add x to result
+        writeln!(f, "@R14")?;
+        writeln!(f, "M=M-D")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "M=D+M")?;
+        writeln!(f, "($$MUL.NEXT)")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "M=D+M")?;
+        writeln!(f, "@R15")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "M=D+M")?;
+        writeln!(f, "@$$MUL.LOOP")?;
+        writeln!(f, "0;JMP")?;
+
+        writeln!(f, "($$MUL.END)")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "0;JMP")
+    }
+
+    /// Routine for `div` and `mod`, R15 holds return address.
+    /// Replaces `x` and `y` on stack with quotient and remainder, keeping SP.
+    ///
+    /// Works with negated absolute values, which unlike positive ones can hold
+    /// -32768. Divisor is doubled while it fits into dividend, every doubled
+    /// value is pushed above stack top, then popped back subtracting
+    /// from dividend, which gives quotient bits from highest to lowest
+    fn write_divmod_routine<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "// Division routine")?;
+        writeln!(f, "($$DIVMOD)")?;
+        // R13 = r = -|x|, R14 = q = 0
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@$$DIVMOD.XNEG")?;
+        writeln!(f, "D;JLE")?;
+        writeln!(f, "D=-D")?;
+        writeln!(f, "($$DIVMOD.XNEG)")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "M=0")?;
+        writeln!(f, "@$$DIVMOD.SIGN")?;
+        writeln!(f, "D;JEQ")?;
+
+        // D = d = -|y|, division by zero gives q = 0, r = x
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@$$DIVMOD.SIGN")?;
+        writeln!(f, "D;JEQ")?;
+        writeln!(f, "@$$DIVMOD.YNEG")?;
+        writeln!(f, "D;JLT")?;
+        writeln!(f, "D=-D")?;
+        writeln!(f, "($$DIVMOD.YNEG)")?;
+
+        // Push 0 marking the end of divisors, then d
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "M=0")?;
+        writeln!(f, "A=A+1")?;
+        writeln!(f, "M=D")?;
+        Self::restore_sp(f)?;
+
+        writeln!(f, "($$DIVMOD.DOUBLE)")?;
+        // Stop if |r| < |d|
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@$$DIVMOD.DIVIDE")?;
+        writeln!(f, "D;JGT")?;
+        // Stop if |r| - |d| < |d|, computed as d - (r - d) which can not overflow
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@$$DIVMOD.DIVIDE")?;
+        writeln!(f, "D;JLT")?;
+        // Push 2d
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "D=D+M")?;
+        writeln!(f, "A=A+1")?;
+        writeln!(f, "M=D")?;
+        Self::restore_sp(f)?;
+        writeln!(f, "@$$DIVMOD.DOUBLE")?;
+        writeln!(f, "0;JMP")?;
+
+        writeln!(f, "($$DIVMOD.DIVIDE)")?;
+        Self::pop_into_d_reg(f)?;
+        writeln!(f, "@$$DIVMOD.SIGN")?;
+        writeln!(f, "D;JEQ")?;
+        // q = 2q + 1 and r -= d if |r| >= |d|, otherwise q = 2q
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@$$DIVMOD.ZERO")?;
+        writeln!(f, "D;JGT")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "D=1")?;
+        writeln!(f, "@$$DIVMOD.BIT")?;
+        writeln!(f, "0;JMP")?;
+        writeln!(f, "($$DIVMOD.ZERO)")?;
+        writeln!(f, "D=0")?;
+        writeln!(f, "($$DIVMOD.BIT)")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "D=D+M")?;
+        writeln!(f, "M=D+M")?;
+        writeln!(f, "@$$DIVMOD.DIVIDE")?;
+        writeln!(f, "0;JMP")?;
+
+        // Quotient is negative if signs of x and y differ,
+        // remainder has sign of x
+        writeln!(f, "($$DIVMOD.SIGN)")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@$$DIVMOD.YPOS")?;
+        writeln!(f, "D;JGE")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "M=-M")?;
+        writeln!(f, "($$DIVMOD.YPOS)")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@$$DIVMOD.XPOS")?;
+        writeln!(f, "D;JGE")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "M=-M")?;
+        writeln!(f, "@$$DIVMOD.STORE")?;
+        writeln!(f, "0;JMP")?;
+        writeln!(f, "($$DIVMOD.XPOS)")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "M=-M")?;
+
+        writeln!(f, "($$DIVMOD.STORE)")?;
+        writeln!(f, "@R14")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "@R13")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M-1")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "@R15")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "0;JMP")
+    }
+
+    /// Pops one value into D register and other will be in M register
+    /// without decreasing SP
+    #[inline]
+    fn pop_before_op<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "// Pop two values from stack")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "A=A-1")?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn push_from_d_reg<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "// Push to stack from D reg")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "A=M")?; // Goto stack top
+        writeln!(f, "M=D")?; // push data to stack
+        writeln!(f, "D=A+1")?; // increment stack pointer
+        writeln!(f, "@SP")?;
+        writeln!(f, "M=D")?; // write stack top to stack pointer registry
+
+        Ok(())
+    }
+
+    #[inline]
+    fn pop_into_d_reg<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "@SP")?;
+        writeln!(f, "AM=M-1")?;
+        writeln!(f, "D=M")?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn compare_and_write<W: Write>(
+        cmd: &ArtithmeticOperation,
+        label: &str,
+        ne_label: &str,
+        f: &mut W,
+    ) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@{}", label)?;
+        write!(f, "D;")?;
+
+        match cmd {
+            Eq => writeln!(f, "JEQ")?,
+            Lt => writeln!(f, "JLT")?,
+            Gt => writeln!(f, "JGT")?,
+            _ => panic!("Can't use jump with operation: {:?}", cmd),
+        }
+
+        writeln!(f, "D=0")?;
+        writeln!(f, "@{}", ne_label)?;
+        writeln!(f, "0;JMP")?;
+        writeln!(f, "({label})")?;
+        writeln!(f, "D=-1")?;
+        writeln!(f, "({ne_label})")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "AM=M-1")?;
+        writeln!(f, "A=A-1")?;
+        writeln!(f, "M=D")?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn restore_sp<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "D=A+1")?; // Inc stack pointer and store stack top in D reg
+        writeln!(f, "@SP")?;
+        writeln!(f, "M=D")?; // Write stack top to SP
+
+        Ok(())
+    }
+
+    fn write_return<W: Write>(f: &mut W) -> Result<()> {
+        writeln!(f, "@LCL")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@R13")?; // Frame reg
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@5")?;
+        writeln!(f, "D=D-A")?;
+        writeln!(f, "A=D")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@R14")?; // Return address
+        writeln!(f, "M=D")?;
+
+        Self::pop_into_d_reg(f)?;
+        writeln!(f, "@ARG")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@ARG")?;
+        writeln!(f, "D=M+1")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "M=D")?;
+
+        ["@NONE_HERE", "@THAT", "@THIS", "@ARG", "@LCL"]
+            .iter()
+            .enumerate()
+            .skip(1)
+            .try_for_each(|(index, addr)| {
+                writeln!(f, "@R13")?;
+                writeln!(f, "D=M")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "D=D-A")?;
+                writeln!(f, "A=D")?;
+                writeln!(f, "D=M")?;
+                writeln!(f, "{addr}")?;
+                writeln!(f, "M=D")?;
+
+                Ok::<(), std::io::Error>(())
+            })?;
+
+        writeln!(f, "@R14")?;
+        writeln!(f, "A=M")?;
+        writeln!(f, "0;JMP")?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn register_to_stack<W: Write>(f: &mut W, name: &str) -> Result<()> {
+        writeln!(f, "@{name}")?;
+        writeln!(f, "D=M")?;
+        Self::push_from_d_reg(f)?;
+
+        Ok(())
+    }
+
+    /// Loads value of `segment index` into D register
+    fn load_into_d_reg<W: Write>(&self, f: &mut W, segment: &Segment, index: u16) -> Result<()> {
+        use Segment::*;
+
+        match segment {
+            Constant => {
+                writeln!(f, "// PUSH constant {index}")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "D=A")?;
+            }
+            Static => {
+                writeln!(f, "@{}.{}", self.module, index)?;
+                writeln!(f, "D=M")?;
+            }
+            Temp | Pointer => {
+                if let Pointer = segment {
+                    writeln!(f, "@THIS")?;
+                } else {
+                    writeln!(f, "@R5")?;
+                }
+
+                writeln!(f, "D=A")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "A=D+A")?;
+                writeln!(f, "D=M")?;
+            }
+            Local | Argument | That | This => {
+                match segment {
+                    Local => writeln!(f, "@LCL")?,
+                    Argument => writeln!(f, "@ARG")?,
+                    This => writeln!(f, "@THIS")?,
+                    That => writeln!(f, "@THAT")?,
+                    _ => unreachable!(),
+                }
+
+                writeln!(f, "D=M")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "A=D+A")?;
+                writeln!(f, "D=M")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads RAM address of `segment index` into D register
+    fn address_into_d_reg<W: Write>(&self, f: &mut W, segment: &Segment, index: u16) -> Result<()> {
+        use Segment::*;
+
+        match segment {
+            Constant => unreachable!(),
+            Static => {
+                writeln!(f, "@{}.{}", self.module, index)?;
+                writeln!(f, "D=A")?;
+            }
+            Temp | Pointer => {
+                match segment {
+                    Temp => writeln!(f, "@R5")?,
+                    Pointer => writeln!(f, "@THIS")?,
+                    _ => unreachable!(),
+                }
+
+                writeln!(f, "D=A")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "D=D+A")?;
+            }
+            _ => {
+                match segment {
+                    Argument => writeln!(f, "@ARG")?,
+                    Local => writeln!(f, "@LCL")?,
+                    This => writeln!(f, "@THIS")?,
+                    That => writeln!(f, "@THAT")?,
+                    _ => unreachable!(),
+                };
+
+                // Calculate result RAM
+                writeln!(f, "D=M")?;
+                writeln!(f, "@{index}")?;
+                writeln!(f, "D=D+A")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Address known at assembly time, so it can be used in A-instruction directly
+    fn fixed_address(&self, segment: &Segment, index: u16) -> Option<String> {
+        match segment {
+            Segment::Static => Some(format!("{}.{}", self.module, index)),
+            Segment::Temp => Some((5 + index).to_string()),
+            Segment::Pointer => Some((3 + index).to_string()),
+            _ => None,
+        }
+    }
+
+    fn write_arithmetic<W: Write>(&mut self, f: &mut W, op: &ArtithmeticOperation) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        if let Eq | Lt | Gt = op {
+            return self.write_comparison(f, op);
+        }
+
+        if op.is_extension() {
+            return self.write_extension(f, op);
+        }
+
+        Self::pop_before_op(f)?;
+        match op {
+            Add => {
+                writeln!(f, "// ADD")?;
+                writeln!(f, "M=D+M")?;
+            }
+            Sub => {
+                writeln!(f, "// Sub")?;
+                writeln!(f, "M=M-D")?;
+            }
+            Neg => {
+                writeln!(f, "// Neg")?;
+                writeln!(f, "A=A+1")?;
+                writeln!(f, "M=-D")?;
+            }
+            And => {
+                writeln!(f, "// And")?;
+                writeln!(f, "M=D&M")?;
+            }
+            Or => {
+                writeln!(f, "// OR")?;
+                writeln!(f, "M=D|M")?;
+            }
+            Not => {
+                writeln!(f, "// Not")?;
+                writeln!(f, "A=A+1")?;
+                writeln!(f, "M=!D")?;
+            }
+            Eq | Lt | Gt | Mul | Div | Mod => unreachable!(),
+        }
+        Self::restore_sp(f)?;
+
+        Ok(())
+    }
+
+    fn write_comparison<W: Write>(&mut self, f: &mut W, op: &ArtithmeticOperation) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        let v = self.label_count;
+
+        if self.compact {
+            let routine = match op {
+                Eq => "$$EQ",
+                Gt => "$$GT",
+                Lt => "$$LT",
+                _ => unreachable!(),
+            };
+            self.label_count += 1;
+
+            writeln!(f, "@$$L{v}")?;
+            writeln!(f, "D=A")?;
+            writeln!(f, "@R15")?;
+            writeln!(f, "M=D")?;
+            writeln!(f, "@{routine}")?;
+            writeln!(f, "0;JMP")?;
+            writeln!(f, "($$L{v})")?;
+
+            return Ok(());
+        }
+
+        self.label_count += 2;
+        Self::pop_before_op(f)?;
+        Self::compare_and_write(op, &format!("$$L{}", v), &format!("$$L{}", v + 1), f)
+    }
+
+    /// Jumps into `$$MUL` or `$$DIVMOD` routine, see `write_mul_routine`
+    /// and `write_divmod_routine`
+    fn write_extension<W: Write>(&mut self, f: &mut W, op: &ArtithmeticOperation) -> Result<()> {
+        use ArtithmeticOperation::*;
+
+        let v = self.label_count;
+        self.label_count += 1;
+
+        let routine = if let Mul = op {
+            self.uses_mul = true;
+            "$$MUL"
+        } else {
+            self.uses_divmod = true;
+            "$$DIVMOD"
+        };
+
+        writeln!(f, "// {op}")?;
+        writeln!(f, "@$$L{v}")?;
+        writeln!(f, "D=A")?;
+        writeln!(f, "@R15")?;
+        writeln!(f, "M=D")?;
+        writeln!(f, "@{routine}")?;
+        writeln!(f, "0;JMP")?;
+        writeln!(f, "($$L{v})")?;
+
+        match op {
+            // Drop remainder
+            Div => {
+                writeln!(f, "@SP")?;
+                writeln!(f, "M=M-1")?;
+            }
+            // Move remainder in place of quotient
+            Mod => {
+                Self::pop_into_d_reg(f)?;
+                writeln!(f, "A=A-1")?;
+                writeln!(f, "M=D")?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn write_call<W: Write>(&mut self, f: &mut W, name: &str, args: u16) -> Result<()> {
+        let return_label = format!("{}$ret.{}", self.scope(), self.call_count);
+        self.call_count += 1;
+
+        if self.compact {
+            writeln!(f, "@{args}")?;
+            writeln!(f, "D=A")?;
+            writeln!(f, "@R13")?;
+            writeln!(f, "M=D")?;
+            writeln!(f, "@{name}")?;
+            writeln!(f, "D=A")?;
+            writeln!(f, "@R14")?;
+            writeln!(f, "M=D")?;
+            writeln!(f, "@{return_label}")?;
+            writeln!(f, "D=A")?;
+            writeln!(f, "@$$CALL")?;
+            writeln!(f, "0;JMP")?;
+            writeln!(f, "({return_label})")?;
+
+            return Ok(());
+        }
+
+        // Pushing return address to stack
+        writeln!(f, "@{return_label}")?;
+        writeln!(f, "D=A")?;
+        Self::push_from_d_reg(f)?;
+
+        Self::register_to_stack(f, "LCL")?;
+        Self::register_to_stack(f, "ARG")?;
+        Self::register_to_stack(f, "THIS")?;
+        Self::register_to_stack(f, "THAT")?;
+
+        // ARG = SP - n - 5 (n - number of arguments to function)
+        writeln!(f, "@5")?;
+        writeln!(f, "D=A")?;
+        writeln!(f, "@{}", args)?;
+        writeln!(f, "D=D+A")?;
+        writeln!(f, "@SP")?;
+        writeln!(f, "D=M-D")?;
+        writeln!(f, "@ARG")?;
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@SP")?;
+        writeln!(f, "D=M")?;
+        writeln!(f, "@LCL")?;
+        writeln!(f, "M=D")?;
+
+        writeln!(f, "@{name}")?;
+        writeln!(f, "0;JMP")?;
+
+        writeln!(f, "({return_label})")?;
+
+        Ok(())
+    }
+}
+
+impl CodeGenerator for HackGenerator {
+    fn bootstrap<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        // Initializing stack. Must be included once
+        writeln!(writer, "@256")?;
+        writeln!(writer, "D=A")?;
+        writeln!(writer, "@SP")?;
+        writeln!(writer, "M=D")?;
+
+        self.function = "Bootstrap".to_owned();
+        self.write_call(writer, "Sys.init", 0)?;
+
+        // Sys.init never returns, so routines can be placed right after its call
+        if self.compact {
+            Self::write_shared_routines(writer)?;
+        }
+
+        Ok(())
+    }
+
+    fn start_file<W: Write>(&mut self, file: &VmFile, writer: &mut W) -> Result<()> {
+        self.module = file.module().to_owned();
+        self.function.clear();
+        self.call_count = 0;
+
+        writeln!(writer, "// File: {}", file.name)
+    }
+
+    fn write_command<W: Write>(&mut self, command: &VmCommand, f: &mut W) -> Result<()> {
+        use VmCommand::*;
+
+        match command {
+            Push { segment, index } => {
+                self.load_into_d_reg(f, segment, *index)?;
+
+                // Push to stack from D register
+                Self::push_from_d_reg(f)?;
+            }
+            Pop { segment, index } => {
+                self.address_into_d_reg(f, segment, *index)?;
+
+                // Save to R15 reg as temporary storage
+                writeln!(f, "@R15")?;
+                writeln!(f, "M=D")?;
+
+                writeln!(f, "@SP")?;
+                writeln!(f, "AM=M-1")?;
+                writeln!(f, "D=M")?;
+
+                writeln!(f, "@R15")?;
+                writeln!(f, "A=M")?;
+                writeln!(f, "M=D")?;
+            }
+            Arithmetic(op) => self.write_arithmetic(f, op)?,
+            Label(label) => {
+                writeln!(f, "({})", self.scoped_label(label))?;
+            }
+            Goto(label) => {
+                writeln!(f, "@{}", self.scoped_label(label))?;
+                writeln!(f, "0;JMP")?;
+            }
+            If(label) => {
+                writeln!(f, "@SP")?;
+                writeln!(f, "AM=M-1")?;
+                writeln!(f, "D=M")?;
+                writeln!(f, "@{}", self.scoped_label(label))?;
+                writeln!(f, "D;JNE")?;
+            }
+            Function { name, locals } => {
+                self.function = name.clone();
+                self.call_count = 0;
+
+                writeln!(f, "({name})")?;
+                (0..*locals).try_for_each(|_| {
+                    writeln!(f, "D=0")?;
+                    Self::push_from_d_reg(f)
+                })?;
+            }
+            Call { name, args } => self.write_call(f, name, *args)?,
+            Return => {
+                if self.compact {
+                    writeln!(f, "@$$RETURN")?;
+                    writeln!(f, "0;JMP")?;
+                } else {
+                    Self::write_return(f)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves value without the stack and jumps on `not x` without computing it
+    fn write_instruction<W: Write>(&mut self, instruction: &Instruction, f: &mut W) -> Result<()> {
+        match instruction {
+            Instruction::Command(command) => self.write_command(command, f)?,
+            Instruction::Move { from, to } => {
+                let (segment, index) = from;
+                let (to_segment, to_index) = to;
+
+                writeln!(f, "// Move {segment} {index} -> {to_segment} {to_index}")?;
+                match self.fixed_address(to_segment, *to_index) {
+                    Some(address) => {
+                        self.load_into_d_reg(f, segment, *index)?;
+                        writeln!(f, "@{address}")?;
+                        writeln!(f, "M=D")?;
+                    }
+                    None => {
+                        self.address_into_d_reg(f, to_segment, *to_index)?;
+                        writeln!(f, "@R15")?;
+                        writeln!(f, "M=D")?;
+
+                        self.load_into_d_reg(f, segment, *index)?;
+                        writeln!(f, "@R15")?;
+                        writeln!(f, "A=M")?;
+                        writeln!(f, "M=D")?;
+                    }
+                }
+            }
+            Instruction::IfNot(label) => {
+                // `not x` is non-zero exactly when `x + 1` is non-zero
+                writeln!(f, "@SP")?;
+                writeln!(f, "AM=M-1")?;
+                writeln!(f, "D=M+1")?;
+                writeln!(f, "@{}", self.scoped_label(label))?;
+                writeln!(f, "D;JNE")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits routines for VM extensions used by program, after its last function
+    fn finish<W: Write>(&mut self, f: &mut W) -> Result<()> {
+        if self.uses_mul {
+            Self::write_mul_routine(f)?;
+        }
+        if self.uses_divmod {
+            Self::write_divmod_routine(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::Cpu, vm::parse_program};
+
+    fn generate(program: &str, compact: bool) -> String {
+        let mut generator = HackGenerator::new(compact);
+        let mut buf = vec![];
+        generator
+            .start_file(&VmFile::new("Main.vm", vec![]), &mut buf)
+            .unwrap();
+        for command in parse_program(program).unwrap() {
+            generator.write_command(&command, &mut buf).unwrap();
+        }
+
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// Runs `Sys.vm` program after bootstrap until it reaches its final loop
+    fn run(program: &str, compact: bool) -> Vec<i16> {
+        let mut generator = HackGenerator::new(compact);
+        let mut buf = vec![];
+        generator.bootstrap(&mut buf).unwrap();
+        generator
+            .start_file(&VmFile::new("Sys.vm", vec![]), &mut buf)
+            .unwrap();
+        for command in parse_program(program).unwrap() {
+            generator.write_command(&command, &mut buf).unwrap();
+        }
+        generator.finish(&mut buf).unwrap();
+
+        let mut cpu = Cpu::new(&String::from_utf8(buf).unwrap());
+        cpu.run(100_000);
+        cpu.ram
+    }
+
+    #[test]
+    fn compact_routines_compute_like_inline_code() {
+        let program = "function Sys.init 0
+push constant 3
+push constant 4
+call Sys.max 2
+pop static 0
+push constant 5
+push constant 5
+eq
+pop static 1
+push constant 2
+push constant 7
+gt
+pop static 2
+push constant 2
+push constant 7
+lt
+pop static 3
+label END
+goto END
+function Sys.max 1
+push argument 0
+push argument 1
+gt
+pop local 0
+push local 0
+if-goto FIRST
+push argument 1
+return
+label FIRST
+push argument 0
+return";
+
+        for compact in [true, false] {
+            let ram = run(program, compact);
+            // Sys.init frame stays on stack, everything above it was popped
+            assert_eq!(ram[0], 261, "compact: {compact}");
+            assert_eq!(ram[16..20], [4, -1, 0, -1], "compact: {compact}");
+        }
+    }
+
+    #[test]
+    fn multiplication_and_division_follow_signed_semantics() {
+        let cases: [(i16, i16); 12] = [
+            (7, 3),
+            (-7, 3),
+            (7, -3),
+            (-7, -3),
+            (0, -5),
+            (5, 0),
+            (-32768, 1),
+            (-32768, -1),
+            (-32768, 2),
+            (-32768, 7),
+            (100, -32768),
+            (181, 181),
+        ];
+        // `push constant` takes only 0..=32767
+        let push = |value: i16| match value {
+            i16::MIN => "push constant 32767\nneg\npush constant 1\nsub\n".to_owned(),
+            value if value < 0 => format!("push constant {}\nneg\n", -value),
+            value => format!("push constant {value}\n"),
+        };
+
+        let mut program = String::from("function Sys.init 0\n");
+        let mut expected = vec![];
+        for (a, b) in cases {
+            for (index, op) in ["mul", "div", "mod"].iter().enumerate() {
+                program += &push(a);
+                program += &push(b);
+                program += &format!("{op}\npop static {}\n", expected.len() + index);
+            }
+            expected.push(a.wrapping_mul(b));
+            expected.push(if b == 0 { 0 } else { a.wrapping_div(b) });
+            expected.push(if b == 0 { a } else { a.wrapping_rem(b) });
+        }
+        program += "label END\ngoto END";
+
+        for compact in [true, false] {
+            let ram = run(&program, compact);
+            assert_eq!(ram[16..16 + expected.len()], expected, "compact: {compact}");
+        }
+    }
+
+    #[test]
+    fn labels_outside_functions_are_scoped_by_module() {
+        let mut generator = HackGenerator::new(false);
+        let mut buf = vec![];
+        for module in ["A.vm", "B.vm"] {
+            generator
+                .start_file(&VmFile::new(module, vec![]), &mut buf)
+                .unwrap();
+            for command in parse_program("call Foo.f 0\nlabel L0\neq").unwrap() {
+                generator.write_command(&command, &mut buf).unwrap();
+            }
+        }
+        let asm = String::from_utf8(buf).unwrap();
+
+        for label in [
+            "(A$ret.0)",
+            "(B$ret.0)",
+            "(A$L0)",
+            "(B$L0)",
+            "($$L0)",
+            "($$L2)",
+        ] {
+            assert_eq!(asm.matches(label).count(), 1, "{label}");
+        }
+        assert!(!asm.contains("(L0)"));
+    }
+
+    #[test]
+    fn compact_call_jumps_into_shared_routine() {
+        let asm = generate("function Main.main 0\ncall Main.f 2", true);
+
+        assert!(asm.contains("@$$CALL"));
+        assert!(asm.contains("(Main.main$ret.0)"));
+        assert!(!asm.contains("@LCL"));
+    }
+
+    #[test]
+    fn compact_mode_is_shorter() {
+        let program = "push constant 1\npush constant 2\neq\ncall Main.f 1\nreturn";
+
+        assert!(generate(program, true).lines().count() < generate(program, false).lines().count());
+    }
+
+    #[test]
+    fn return_labels_are_counted_per_caller() {
+        let asm = generate(
+            "function Main.f 0\ncall Main.g 0\ncall Main.g 0\nfunction Main.g 0\ncall Main.f 0",
+            false,
+        );
+
+        assert!(asm.contains("(Main.f$ret.0)"));
+        assert!(asm.contains("(Main.f$ret.1)"));
+        assert!(asm.contains("(Main.g$ret.0)"));
+    }
+
+    #[test]
+    fn labels_are_scoped_to_function() {
+        let asm = generate("function Main.f 0\nlabel LOOP\ngoto LOOP", false);
+
+        assert!(asm.contains("(Main.f$LOOP)"));
+        assert!(asm.contains("@Main.f$LOOP"));
+    }
+
+    #[test]
+    fn statics_are_named_by_class() {
+        let asm = generate(
+            "push static 0
+pop static 3",
+            false,
+        );
+
+        assert!(asm.contains("@Main.0\n"));
+        assert!(asm.contains("@Main.3\n"));
+        assert!(!asm.contains("Main.vm."));
+    }
+
+    #[test]
+    fn extension_routines_are_emitted_once_when_used() {
+        let mut generator = HackGenerator::new(false);
+        let mut buf = vec![];
+        for command in parse_program("mul\nmul\ndiv\nmod").unwrap() {
+            generator.write_command(&command, &mut buf).unwrap();
+        }
+        generator.finish(&mut buf).unwrap();
+        let asm = String::from_utf8(buf).unwrap();
+
+        assert_eq!(asm.matches("($$MUL)").count(), 1);
+        assert_eq!(asm.matches("($$DIVMOD)").count(), 1);
+        assert_eq!(asm.matches("@$$DIVMOD\n").count(), 2);
+
+        let mut buf = vec![];
+        generator = HackGenerator::new(false);
+        generator.finish(&mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+}
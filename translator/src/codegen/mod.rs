@@ -0,0 +1,45 @@
+pub mod c;
+pub mod hack;
+
+use std::io::{Result, Write};
+
+use crate::{
+    optimizer::Instruction,
+    vm::{VmCommand, VmFile},
+};
+
+/// Backend turning VM commands into target code.
+///
+/// `Translator` calls `bootstrap` once, then `start_file` for every `.vm` file
+/// followed by `write_instruction` for each of its commands, and `finish` at the end.
+/// All state needed between calls (labels, current file or function) lives in the generator.
+pub trait CodeGenerator {
+    fn bootstrap<W: Write>(&mut self, writer: &mut W) -> Result<()>;
+    fn start_file<W: Write>(&mut self, file: &VmFile, writer: &mut W) -> Result<()>;
+    fn write_command<W: Write>(&mut self, command: &VmCommand, writer: &mut W) -> Result<()>;
+
+    /// Writes command or pair fused by optimizer. Backends without shorter
+    /// code for a pair write its commands one by one
+    fn write_instruction<W: Write>(
+        &mut self,
+        instruction: &Instruction,
+        writer: &mut W,
+    ) -> Result<()> {
+        match instruction {
+            Instruction::Command(command) => self.write_command(command, writer),
+            fused => fused
+                .commands()
+                .iter()
+                .try_for_each(|command| self.write_command(command, writer)),
+        }
+    }
+
+    /// Writes single line comment, used to annotate output with VM source
+    fn comment<W: Write>(&mut self, text: &str, writer: &mut W) -> Result<()> {
+        writeln!(writer, "// {text}")
+    }
+
+    fn finish<W: Write>(&mut self, _writer: &mut W) -> Result<()> {
+        Ok(())
+    }
+}
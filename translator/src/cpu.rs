@@ -0,0 +1,91 @@
+//! Hack CPU, used by tests to run generated code and inspect RAM
+use assembler::assembler::Assembler;
+
+pub struct Cpu {
+    pub ram: Vec<i16>,
+    rom: Vec<u16>,
+    a: i16,
+    d: i16,
+    pc: usize,
+}
+
+impl Cpu {
+    /// Assembles `asm` and loads it into ROM
+    pub fn new(asm: &str) -> Self {
+        let mut buf = vec![];
+        Assembler::new(asm).write(&mut buf).unwrap();
+        let rom = String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .map(|line| u16::from_str_radix(line, 2).unwrap())
+            .collect();
+
+        Self {
+            ram: vec![0; 1 << 15],
+            rom,
+            a: 0,
+            d: 0,
+            pc: 0,
+        }
+    }
+
+    fn step(&mut self) {
+        let instruction = self.rom[self.pc];
+        self.pc += 1;
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            return;
+        }
+
+        let address = self.a as u16 as usize;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let bit = |n: u16| instruction & (1 << n) != 0;
+
+        let mut x = if bit(11) { 0 } else { self.d };
+        if bit(10) {
+            x = !x;
+        }
+        let mut y = if bit(9) { 0 } else { y };
+        if bit(8) {
+            y = !y;
+        }
+        let mut out = if bit(7) { x.wrapping_add(y) } else { x & y };
+        if bit(6) {
+            out = !out;
+        }
+
+        if bit(3) {
+            self.ram[address] = out;
+        }
+        if bit(4) {
+            self.d = out;
+        }
+        if bit(5) {
+            self.a = out;
+        }
+
+        let jump = (bit(2) && out < 0) || (bit(1) && out == 0) || (bit(0) && out > 0);
+        if jump {
+            self.pc = address;
+        }
+    }
+
+    /// Runs until program reaches `(END) @END 0;JMP` style loop.
+    /// Panics after `limit` instructions
+    pub fn run(&mut self, limit: usize) {
+        for _ in 0..limit {
+            let pc = self.pc;
+            self.step();
+            if self.pc + 1 == pc && self.rom[self.pc] == self.pc as u16 {
+                return;
+            }
+        }
+
+        panic!("program did not halt after {limit} instructions");
+    }
+}
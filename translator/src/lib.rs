@@ -1,2 +1,14 @@
+pub mod bytecode;
+pub mod callgraph;
+pub mod codegen;
+#[cfg(test)]
+mod cpu;
+pub mod json;
+pub mod optimizer;
 pub mod parser;
+pub mod prune;
+pub mod source_map;
+pub mod statics;
 pub mod translator;
+pub mod verifier;
+pub mod vm;
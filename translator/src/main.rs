@@ -5,23 +5,105 @@ use std::{
     path::Path,
 };
 
-use translator::translator::Translator;
+use translator::{codegen::c::CGenerator, translator::Translator};
 
 fn main() -> Result<(), io::Error> {
-    let mut args = env::args();
+    let mut compact = false;
+    let mut optimize = false;
+    let mut emit_optimized_vm = false;
+    let mut target_c = false;
+    let mut verify = true;
+    let mut report_statics = false;
+    let mut prune = false;
+    let mut annotate = false;
+    let mut source_map = false;
+    let mut assemble = false;
+    let mut call_graph = None;
+    let mut extensions = false;
+    let mut libraries = vec![];
+    let mut paths = vec![];
 
-    if args.len() < 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "no filename passed!"));
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compact" => compact = true,
+            "--optimize" => optimize = true,
+            "--emit-optimized-vm" => emit_optimized_vm = true,
+            "--target=hack" => target_c = false,
+            "--target=c" => target_c = true,
+            "--no-verify" => verify = false,
+            "--report-statics" => report_statics = true,
+            "--prune" => prune = true,
+            "--annotate" => annotate = true,
+            "--source-map" => source_map = true,
+            "--assemble" => assemble = true,
+            "--extensions" => extensions = true,
+            "--call-graph=dot" => call_graph = Some("dot"),
+            "--call-graph=json" => call_graph = Some("json"),
+            "-L" => match args.next() {
+                Some(dir) => libraries.push(dir),
+                None => return Err(io::Error::other("-L needs a directory")),
+            },
+            flag if flag.starts_with("-L") => libraries.push(flag[2..].to_owned()),
+            flag if flag.starts_with("--") => {
+                return Err(io::Error::other(format!("unknown option: {flag}")));
+            }
+            _ => paths.push(arg),
+        }
     }
 
-    if args.len() > 2 {
-        return Err(io::Error::new(io::ErrorKind::Other, "too many arguments"));
+    if paths.is_empty() {
+        return Err(io::Error::other("no filename passed!"));
     }
 
-    let file_path = args.nth(1).unwrap();
-    let file_path: &Path = file_path.as_ref();
+    if paths.len() > 1 {
+        return Err(io::Error::other("too many arguments"));
+    }
+
+    if assemble && (target_c || source_map) {
+        return Err(io::Error::other(
+            "--assemble can not be combined with --target=c or --source-map",
+        ));
+    }
+
+    let file_path: &Path = paths[0].as_ref();
 
     let mut translator = Translator::new(file_path)?;
+    for dir in &libraries {
+        translator = translator.library(dir.as_ref())?;
+    }
+
+    let mut translator = translator
+        .compact(compact)
+        .optimize(optimize || emit_optimized_vm)
+        .verify(verify)
+        .prune(prune)
+        .annotate(annotate)
+        .extensions(extensions);
+
+    if prune {
+        eprintln!("{}", translator.pruned()?);
+    }
+
+    if report_statics {
+        eprintln!("{}", translator.statics()?);
+    }
+
+    // Print call graph to stdout instead of translating
+    if let Some(format) = call_graph {
+        let graph = translator.call_graph()?;
+        let stdout = BufWriter::new(io::stdout().lock());
+
+        return match format {
+            "dot" => graph.write_dot(stdout),
+            _ => graph.write_json(stdout),
+        };
+    }
+
+    // Dump optimized VM code to stdout instead of translating it
+    if emit_optimized_vm {
+        return translator.emit_vm(BufWriter::new(io::stdout().lock()));
+    }
 
     let mut asm_file;
     if file_path.is_file() {
@@ -36,10 +118,29 @@ fn main() -> Result<(), io::Error> {
         panic!("Provided string neigher path nor file");
     }
 
-    asm_file.set_extension("asm");
-    let file = BufWriter::new(File::create(asm_file)?);
+    // Translate and assemble in memory, writing only `.hack` file
+    if assemble {
+        asm_file.set_extension("hack");
+        return translator.emit_hack(BufWriter::new(File::create(asm_file)?));
+    }
+
+    let map_file = asm_file.with_extension("map.json");
 
-    translator.emit(file)?;
+    let map = if target_c {
+        asm_file.set_extension("c");
+        let file = BufWriter::new(File::create(asm_file)?);
+
+        translator.emit_with(&mut CGenerator::new(), file)?
+    } else {
+        asm_file.set_extension("asm");
+        let file = BufWriter::new(File::create(asm_file)?);
+
+        translator.emit(file)?
+    };
+
+    if source_map {
+        map.write_json(BufWriter::new(File::create(map_file)?))?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,284 @@
+use core::fmt;
+
+use crate::vm::{ArtithmeticOperation, Segment, VmCommand, VmFile};
+
+/// Input of code generators: VM command, or pair of commands a backend can
+/// translate into shorter code. Fused pairs are not part of the VM language,
+/// so they never appear in `VmCommand` or `.vm` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Command(VmCommand),
+    /// `push from; pop to`
+    Move {
+        from: (Segment, u16),
+        to: (Segment, u16),
+    },
+    /// `not; if-goto label`
+    IfNot(String),
+}
+
+impl Instruction {
+    /// Name of fused pair, `None` for single command
+    pub fn fused_name(&self) -> Option<&'static str> {
+        match self {
+            Instruction::Command(_) => None,
+            Instruction::Move { .. } => Some("move"),
+            Instruction::IfNot(_) => Some("if-not"),
+        }
+    }
+
+    /// VM commands the instruction stands for
+    pub fn commands(&self) -> Vec<VmCommand> {
+        match self {
+            Instruction::Command(command) => vec![command.clone()],
+            Instruction::Move { from, to } => {
+                vec![VmCommand::push(from.0, from.1), VmCommand::pop(to.0, to.1)]
+            }
+            Instruction::IfNot(label) => vec![
+                VmCommand::arithmetic(ArtithmeticOperation::Not),
+                VmCommand::if_goto(label),
+            ],
+        }
+    }
+}
+
+/// Commands of instruction separated by `; `
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands().iter().map(|c| c.to_string()).collect();
+
+        write!(f, "{}", commands.join("; "))
+    }
+}
+
+/// Peephole optimizer working on VM commands of a single file.
+///
+/// Each command is appended to the output and the tail of the output is
+/// rewritten while any pattern matches, so folded results can take part
+/// in further folding (e.g. `push constant 1; push constant 2; add; push constant 3; add`).
+/// Command produced by rewriting takes source line of the first rewritten command.
+pub(crate) fn optimize(file: &mut VmFile) {
+    let commands = std::mem::take(&mut file.commands);
+    let lines = std::mem::take(&mut file.lines);
+    let keep_lines = lines.len() == commands.len();
+
+    for (index, command) in commands.into_iter().enumerate() {
+        file.commands.push(command);
+        if keep_lines {
+            file.lines.push(lines[index]);
+        }
+
+        while let Some(start) = rewrite_tail(&mut file.commands) {
+            if keep_lines {
+                let line = file.lines[start];
+                file.lines.truncate(start);
+                file.lines.resize(file.commands.len(), line);
+            }
+        }
+    }
+}
+
+/// Rewrites the last commands of `commands` if they match any pattern.
+/// Returns index of the first rewritten command if something was changed
+fn rewrite_tail(commands: &mut Vec<VmCommand>) -> Option<usize> {
+    use VmCommand::*;
+
+    let len = commands.len();
+
+    // push constant a; push constant b; op -> push constant (a op b)
+    if len >= 3 {
+        if let [Push {
+            segment: Segment::Constant,
+            index: a,
+        }, Push {
+            segment: Segment::Constant,
+            index: b,
+        }, Arithmetic(op)] = &commands[len - 3..]
+        {
+            if let Some(value) = fold(*a, *b, op) {
+                commands.truncate(len - 3);
+                commands.push(Push {
+                    segment: Segment::Constant,
+                    index: value,
+                });
+
+                return Some(len - 3);
+            }
+        }
+    }
+
+    if len < 2 {
+        return None;
+    }
+
+    match &commands[len - 2..] {
+        // push x; pop x -> nothing
+        [Push { segment, index }, Pop {
+            segment: to_segment,
+            index: to_index,
+        }] if segment == to_segment && index == to_index => {
+            commands.truncate(len - 2);
+
+            Some(len - 2)
+        }
+        _ => None,
+    }
+}
+
+/// Fuses `push x; pop y` and `not; if-goto label` of optimized commands
+/// into single instructions. Every instruction comes with index of its first command
+pub(crate) fn fuse(commands: &[VmCommand]) -> Vec<(usize, Instruction)> {
+    use VmCommand::*;
+
+    let mut instructions = Vec::with_capacity(commands.len());
+    let mut index = 0;
+
+    while index < commands.len() {
+        let fused = match &commands[index..] {
+            [Push { segment, index: i }, Pop {
+                segment: to_segment,
+                index: to_index,
+            }, ..] => Some(Instruction::Move {
+                from: (*segment, *i),
+                to: (*to_segment, *to_index),
+            }),
+            [Arithmetic(ArtithmeticOperation::Not), If(label), ..] => {
+                Some(Instruction::IfNot(label.clone()))
+            }
+            _ => None,
+        };
+
+        match fused {
+            Some(instruction) => {
+                instructions.push((index, instruction));
+                index += 2;
+            }
+            None => {
+                instructions.push((index, Instruction::Command(commands[index].clone())));
+                index += 1;
+            }
+        }
+    }
+
+    instructions
+}
+
+/// Computes `a op b` if result can be pushed as constant (0..=32767)
+fn fold(a: u16, b: u16, op: &ArtithmeticOperation) -> Option<u16> {
+    use ArtithmeticOperation::*;
+
+    let value = match op {
+        Add => a.wrapping_add(b),
+        Sub => a.wrapping_sub(b),
+        And => a & b,
+        Or => a | b,
+        Mul => a.wrapping_mul(b),
+        // Both constants are non-negative, so unsigned division matches signed one
+        Div if b != 0 => a / b,
+        Mod if b != 0 => a % b,
+        _ => return None,
+    };
+
+    if value <= i16::MAX as u16 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize_str(program: &str) -> Vec<String> {
+        let mut file = VmFile::new("Main.vm", crate::vm::parse_program(program).unwrap());
+        optimize(&mut file);
+
+        file.commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn rewritten_commands_keep_first_line() {
+        let mut file = VmFile::new(
+            "Main.vm",
+            crate::vm::parse_program(
+                "push local 0
+push constant 1
+push constant 2
+add
+push local 1
+pop local 1
+return",
+            )
+            .unwrap(),
+        );
+        file.lines = vec![1, 3, 4, 5, 7, 8, 9];
+        optimize(&mut file);
+
+        assert_eq!(file.commands.len(), 3);
+        assert_eq!(file.lines, vec![1, 3, 9]);
+    }
+
+    #[test]
+    fn folds_constant_chains() {
+        let result = optimize_str(
+            "push constant 1\npush constant 2\nadd\npush constant 3\nsub\npush constant 4\nadd",
+        );
+
+        assert_eq!(result, vec!["push constant 4"]);
+    }
+
+    #[test]
+    fn does_not_fold_negative_results() {
+        let result = optimize_str("push constant 1\npush constant 2\nsub");
+
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn folds_extensions_except_division_by_zero() {
+        assert_eq!(
+            optimize_str("push constant 6\npush constant 7\nmul\npush constant 5\nmod"),
+            vec!["push constant 2"]
+        );
+        assert_eq!(
+            optimize_str("push constant 6\npush constant 0\ndiv").len(),
+            3
+        );
+    }
+
+    #[test]
+    fn removes_push_pop_to_same_location() {
+        let result = optimize_str("push local 1\npop local 1\nreturn");
+
+        assert_eq!(result, vec!["return"]);
+    }
+
+    #[test]
+    fn fuses_moves_and_inverted_jumps() {
+        let program = "push argument 0\npop static 1\nnot\nif-goto END\nnot\nreturn";
+        let commands = crate::vm::parse_program(program).unwrap();
+
+        // Optimized program stays plain VM code, pairs are fused afterwards
+        assert_eq!(optimize_str(program).len(), 6);
+        assert_eq!(
+            fuse(&commands),
+            vec![
+                (
+                    0,
+                    Instruction::Move {
+                        from: (Segment::Argument, 0),
+                        to: (Segment::Static, 1),
+                    }
+                ),
+                (2, Instruction::IfNot("END".to_owned())),
+                (
+                    4,
+                    Instruction::Command(VmCommand::arithmetic(ArtithmeticOperation::Not))
+                ),
+                (5, Instruction::Command(VmCommand::Return)),
+            ]
+        );
+        assert_eq!(fuse(&commands)[1].1.to_string(), "not; if-goto END");
+    }
+}
@@ -0,0 +1,176 @@
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    verifier::function_bodies,
+    vm::{VmCommand, VmFile},
+};
+
+/// Function called by bootstrap code, the root of every program
+pub const ENTRY_POINT: &str = "Sys.init";
+
+/// Function dropped because it can not be called
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedFunction {
+    pub file: String,
+    pub name: String,
+    /// Number of VM commands removed with function
+    pub commands: usize,
+}
+
+/// Functions removed by `prune`, in program order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub removed: Vec<RemovedFunction>,
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for function in &self.removed {
+            writeln!(
+                f,
+                "{}: removed `{}` ({} commands)",
+                function.file, function.name, function.commands
+            )?;
+        }
+
+        write!(
+            f,
+            "Removed {} unreachable functions, {} commands",
+            self.removed.len(),
+            self.removed.iter().map(|f| f.commands).sum::<usize>()
+        )
+    }
+}
+
+/// Names of all functions reachable from `Sys.init` and from code outside functions
+/// over `call` edges
+fn reachable(files: &[VmFile]) -> HashSet<&str> {
+    let mut calls: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut queue = vec![ENTRY_POINT];
+
+    for file in files {
+        for (name, body) in function_bodies(&file.commands) {
+            let callees = body.iter().filter_map(|command| match command {
+                VmCommand::Call { name, .. } => Some(name.as_str()),
+                _ => None,
+            });
+
+            match name {
+                Some(name) => calls.entry(name).or_default().extend(callees),
+                None => queue.extend(callees),
+            }
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some(name) = queue.pop() {
+        if reachable.insert(name) {
+            if let Some(callees) = calls.get(name) {
+                queue.extend(callees);
+            }
+        }
+    }
+
+    reachable
+}
+
+/// Removes functions which can not be reached from `Sys.init`.
+///
+/// Programs without `Sys.init` (e.g. single test files run without bootstrap)
+/// are left untouched, since any function may be their entry point
+pub fn prune(files: &mut [VmFile]) -> PruneReport {
+    let mut report = PruneReport::default();
+
+    let has_entry = files.iter().any(|file| {
+        file.commands.iter().any(
+            |command| matches!(command, VmCommand::Function { name, .. } if name == ENTRY_POINT),
+        )
+    });
+    if !has_entry {
+        return report;
+    }
+
+    let reachable: HashSet<String> = reachable(files).into_iter().map(String::from).collect();
+
+    for file in files.iter_mut() {
+        let mut keep = vec![true; file.commands.len()];
+        let mut offset = 0;
+
+        for (name, body) in function_bodies(&file.commands) {
+            if let Some(name) = name.filter(|name| !reachable.contains(*name)) {
+                keep[offset..offset + body.len()].fill(false);
+                report.removed.push(RemovedFunction {
+                    file: file.name.clone(),
+                    name: name.to_owned(),
+                    commands: body.len(),
+                });
+            }
+            offset += body.len();
+        }
+
+        let mut kept = keep.iter();
+        file.commands.retain(|_| *kept.next().unwrap());
+        if !file.lines.is_empty() {
+            let mut kept = keep.iter();
+            file.lines.retain(|_| *kept.next().unwrap());
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn file(name: &str, source: &str) -> VmFile {
+        VmFile::new(name, parse_program(source).unwrap())
+    }
+
+    #[test]
+    fn removes_functions_not_reachable_from_sys_init() {
+        let mut files = vec![
+            file(
+                "Main.vm",
+                "function Main.main 0\ncall Main.used 0\nreturn\nfunction Main.unused 0\ncall Main.used 0\nreturn\nfunction Main.used 0\ncall Main.main 0\nreturn",
+            ),
+            file(
+                "Sys.vm",
+                "function Sys.init 0\ncall Main.main 0\nlabel L\ngoto L\nfunction Sys.error 1\nreturn",
+            ),
+        ];
+
+        let report = prune(&mut files);
+
+        assert_eq!(
+            report
+                .removed
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Main.unused", "Sys.error"]
+        );
+        assert_eq!(files[0].commands.len(), 6);
+        assert_eq!(
+            files[1].commands,
+            parse_program("function Sys.init 0\ncall Main.main 0\nlabel L\ngoto L").unwrap()
+        );
+        assert_eq!(
+            report.to_string().lines().last(),
+            Some("Removed 2 unreachable functions, 5 commands")
+        );
+    }
+
+    #[test]
+    fn keeps_everything_without_entry_point() {
+        let mut files = vec![file(
+            "Main.vm",
+            "function Main.a 0\nreturn\nfunction Main.b 0\nreturn",
+        )];
+
+        assert!(prune(&mut files).removed.is_empty());
+        assert_eq!(files[0].commands.len(), 4);
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use crate::json;
+
+/// Assembly produced for a single VM command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapEntry {
+    pub file: String,
+    /// Source line of command, unknown for `.vmb` input
+    pub line: Option<usize>,
+    pub command: String,
+    /// 1-based lines of generated code, empty if command produced no code
+    pub output: RangeInclusive<usize>,
+}
+
+/// Map from VM source lines to lines of generated code, in output order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub entries: Vec<MapEntry>,
+}
+
+impl SourceMap {
+    /// Writes map as JSON array with one entry per line:
+    /// `{"file": "Main.vm", "line": 3, "command": "push constant 1", "start": 20, "end": 26}`
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "[")?;
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let line = entry
+                .line
+                .map_or_else(|| "null".to_owned(), |line| line.to_string());
+            let separator = if index + 1 < self.entries.len() {
+                ","
+            } else {
+                ""
+            };
+
+            writeln!(
+                writer,
+                "  {{\"file\": {}, \"line\": {line}, \"command\": {}, \"start\": {}, \"end\": {}}}{separator}",
+                json::string(&entry.file),
+                json::string(&entry.command),
+                entry.output.start(),
+                entry.output.end(),
+            )?;
+        }
+
+        writeln!(writer, "]")
+    }
+}
+
+/// Writer counting lines passed through it
+pub(crate) struct LineCounter<W: Write> {
+    inner: W,
+    lines: usize,
+}
+
+impl<W: Write> LineCounter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner, lines: 0 }
+    }
+
+    /// Number of complete lines written so far
+    pub(crate) fn lines(&self) -> usize {
+        self.lines
+    }
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|b| **b == b'\n').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_json_entries() {
+        let map = SourceMap {
+            entries: vec![
+                MapEntry {
+                    file: "Main.vm".to_owned(),
+                    line: Some(3),
+                    command: "push constant 1".to_owned(),
+                    output: 20..=26,
+                },
+                MapEntry {
+                    file: "Main.vm".to_owned(),
+                    line: None,
+                    command: "add".to_owned(),
+                    output: 27..=31,
+                },
+            ],
+        };
+
+        let mut buf = vec![];
+        map.write_json(&mut buf).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[\n  {\"file\": \"Main.vm\", \"line\": 3, \"command\": \"push constant 1\", \"start\": 20, \"end\": 26},\n  {\"file\": \"Main.vm\", \"line\": null, \"command\": \"add\", \"start\": 27, \"end\": 31}\n]\n"
+        );
+    }
+}
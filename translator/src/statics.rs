@@ -0,0 +1,135 @@
+use core::fmt;
+use std::collections::BTreeSet;
+
+use crate::vm::{Segment, VmCommand, VmFile};
+
+/// Number of words available for `static` segment, RAM 16-255
+pub const STATIC_CAPACITY: usize = 240;
+
+/// Static variables used by a single class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassStatics {
+    pub class: String,
+    /// Distinct `static` indices used by class, each taking one word
+    pub indices: BTreeSet<u16>,
+}
+
+/// RAM taken by `static` segments of all classes.
+///
+/// Every distinct `Class.index` symbol gets its own word, so the total is
+/// the number of distinct indices summed over classes, not the highest index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticAllocation {
+    pub classes: Vec<ClassStatics>,
+}
+
+impl StaticAllocation {
+    /// Collects statics used by every file, in file order
+    pub fn new(files: &[VmFile]) -> Self {
+        let mut allocation = Self::default();
+
+        for file in files {
+            let indices = file.commands.iter().filter_map(|command| match command {
+                VmCommand::Push {
+                    segment: Segment::Static,
+                    index,
+                }
+                | VmCommand::Pop {
+                    segment: Segment::Static,
+                    index,
+                } => Some(*index),
+                _ => None,
+            });
+
+            match allocation
+                .classes
+                .iter_mut()
+                .find(|class| class.class == file.module())
+            {
+                Some(class) => class.indices.extend(indices),
+                None => allocation.classes.push(ClassStatics {
+                    class: file.module().to_owned(),
+                    indices: indices.collect(),
+                }),
+            }
+        }
+
+        allocation
+    }
+
+    /// Words taken by all classes
+    pub fn total(&self) -> usize {
+        self.classes.iter().map(|class| class.indices.len()).sum()
+    }
+
+    /// Fails if statics do not fit into RAM 16-255
+    pub fn check(&self) -> Result<(), String> {
+        if self.total() > STATIC_CAPACITY {
+            Err(format!(
+                "static variables take {} words, only {STATIC_CAPACITY} are available (RAM 16-255)",
+                self.total()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for StaticAllocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .classes
+            .iter()
+            .map(|class| class.class.len())
+            .max()
+            .unwrap_or(0);
+
+        for class in &self.classes {
+            writeln!(
+                f,
+                "{:width$}  {:>3} words",
+                class.class,
+                class.indices.len()
+            )?;
+        }
+
+        write!(f, "Total: {} of {STATIC_CAPACITY} words", self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn file(name: &str, source: &str) -> VmFile {
+        VmFile::new(name, parse_program(source).unwrap())
+    }
+
+    #[test]
+    fn counts_distinct_indices_per_class() {
+        let allocation = StaticAllocation::new(&[
+            file("Main.vm", "push static 0\npop static 5\npush static 0"),
+            file("Sys.vm", "push constant 1\npop static 0"),
+            file("Empty.vm", "push constant 1"),
+        ]);
+
+        assert_eq!(allocation.total(), 3);
+        assert_eq!(
+            allocation.to_string(),
+            "Main     2 words\nSys      1 words\nEmpty    0 words\nTotal: 3 of 240 words"
+        );
+        assert!(allocation.check().is_ok());
+    }
+
+    #[test]
+    fn rejects_more_than_240_words() {
+        let source: String = (0..241).map(|i| format!("pop static {i}\n")).collect();
+        let allocation = StaticAllocation::new(&[file("Main.vm", &source)]);
+
+        assert_eq!(
+            allocation.check().unwrap_err(),
+            "static variables take 241 words, only 240 are available (RAM 16-255)"
+        );
+    }
+}
@@ -4,37 +4,74 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::parser::Parser;
+use assembler::assembler::Assembler;
+
+use crate::{
+    bytecode,
+    callgraph::CallGraph,
+    codegen::{hack::HackGenerator, CodeGenerator},
+    optimizer::{self, Instruction},
+    parser::Parser,
+    prune::{self, PruneReport},
+    source_map::{LineCounter, MapEntry, SourceMap},
+    statics::StaticAllocation,
+    verifier,
+    vm::{self, VmFile},
+};
 
 pub struct Translator {
     files: Vec<PathBuf>, // Must not contain more than 256 vm files
-                         // parser: Parser,
+    compact: bool,
+    optimize: bool,
+    verify: bool,
+    prune: bool,
+    annotate: bool,
+    extensions: bool,
 }
 
 impl Translator {
+    /// `.vm` and binary `.vmb` files in directory, skipping hidden ones.
+    /// `.vmb` file is skipped if `.vm` file with the same name exists,
+    /// since it is usually assembled from it
+    fn vm_files(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        let mut files = vec![];
+
+        for item in dir.read_dir()?.flatten() {
+            if let Some(ext) = item.path().extension() {
+                if (ext == "vm" || ext == "vmb")
+                    && !item
+                        .path()
+                        .file_name()
+                        .expect("Must have filename")
+                        .to_str()
+                        .expect("Must convert to str normally")
+                        .starts_with(".")
+                {
+                    files.push(item.path())
+                }
+            }
+        }
+
+        let sources: Vec<PathBuf> = files
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+            .cloned()
+            .collect();
+        files.retain(|path| {
+            path.extension().is_some_and(|ext| ext == "vm")
+                || !sources.contains(&path.with_extension("vm"))
+        });
+
+        Ok(files)
+    }
+
     pub fn new(filepath: &Path) -> Result<Self, io::Error> {
         let mut files = vec![];
 
         if filepath.is_dir() {
-            filepath.read_dir().expect("Must be ok").for_each(|item| {
-                if let Ok(item) = item {
-                    if let Some(ext) = item.path().extension() {
-                        if ext == "vm"
-                            && !item
-                                .path()
-                                .file_name()
-                                .expect("Must have filename")
-                                .to_str()
-                                .expect("Must convert to str normally")
-                                .starts_with(".")
-                        {
-                            files.push(item.path())
-                        }
-                    }
-                }
-            });
+            files = Self::vm_files(filepath)?;
 
-            if files.len() == 0 {
+            if files.is_empty() {
                 panic!(
                     "No .vm files found in provided path: {}",
                     filepath.as_os_str().to_string_lossy()
@@ -44,41 +81,480 @@ impl Translator {
             files.push(filepath.to_path_buf());
         }
 
-        Ok(Self { files })
+        Ok(Self::from_files(files))
     }
 
-    pub fn emit<T: Write>(&mut self, mut writer: T) -> Result<(), io::Error> {
-        let mut parser = Parser::new(
-            BufReader::new(File::open(
-                self.files.iter().next().expect("It can't be empty"),
-            )?),
-            "".to_owned(),
-        );
-        // Initializing stack. Must be included once
-        writeln!(writer, "@256")?;
-        writeln!(writer, "D=A")?;
-        writeln!(writer, "@SP")?;
-        writeln!(writer, "M=D")?;
-
-        // Here we need to CALL Sys.init, not just jump to it
-        // writeln!(writer, "@Sys.init")?;
-        // writeln!(writer, "0;JMP")?;
-        writer.write_all(crate::parser::Command::init().to_string().as_bytes())?;
-
-        self.files.iter().try_for_each(|filepath| {
-            let file = BufReader::new(File::open(&filepath)?);
-            let name = filepath.file_name().unwrap().to_string_lossy().to_string();
-            parser.next_file(file, &name);
-
-            writeln!(writer, "// File: {name}")?;
-
-            while let Some(command) = parser.advance() {
-                writer.write_all(command.to_string().as_bytes())?;
+    /// Translator for given `.vm` files. Their order does not matter, see `sort_files`
+    pub fn from_files(mut files: Vec<PathBuf>) -> Self {
+        Self::sort_files(&mut files);
+
+        Self {
+            files,
+            compact: false,
+            optimize: false,
+            verify: true,
+            prune: false,
+            annotate: false,
+            extensions: false,
+        }
+    }
+
+    /// Puts files into translation order, so output does not depend on the order
+    /// in which file system lists them: `Sys.vm` or `Sys.vmb` first, since
+    /// bootstrap calls `Sys.init`, then other files sorted by name
+    fn sort_files(files: &mut [PathBuf]) {
+        files.sort_by_cached_key(|path| {
+            (
+                path.file_stem() != Some("Sys".as_ref()),
+                path.file_name().map(|name| name.to_owned()),
+                path.clone(),
+            )
+        });
+    }
+
+    /// Adds classes from library directory, e.g. shared Jack OS.
+    ///
+    /// Library class is used only if no class with the same name was added
+    /// before, so program files override libraries, and earlier libraries
+    /// override later ones. Function defined in two used files is an error
+    pub fn library(mut self, dir: &Path) -> Result<Self, io::Error> {
+        for file in Self::vm_files(dir)? {
+            if !self
+                .files
+                .iter()
+                .any(|known| known.file_stem() == file.file_stem())
+            {
+                self.files.push(file);
             }
+        }
+        Self::sort_files(&mut self.files);
+
+        Ok(self)
+    }
+
+    /// Size-optimized mode: `call`, `return`, `eq`, `gt` and `lt` jump into
+    /// shared routines emitted once at the start of the program
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// Runs VM level optimizer (see `optimizer` module) before emitting code
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Checks program with `verifier` before translation. Enabled by default
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Drops functions unreachable from `Sys.init` (see `prune` module)
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    /// Precedes code of every VM command with `// file:line: command` comment
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Allows `mul`, `div` and `mod` VM commands
+    pub fn extensions(mut self, extensions: bool) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    fn read_file(filepath: &Path) -> Result<VmFile, io::Error> {
+        let name = filepath.file_name().unwrap().to_string_lossy().to_string();
+
+        if filepath.extension().is_some_and(|ext| ext == "vmb") {
+            let mut file = bytecode::read_module(BufReader::new(File::open(filepath)?))
+                .map_err(|err| io::Error::new(err.kind(), format!("{name}: {err}")))?;
+            file.name = name;
+            return Ok(file);
+        }
+
+        let mut parser = Parser::new(BufReader::new(File::open(filepath)?));
+        let mut file = VmFile::new(name, vec![]);
+
+        while let Some(command) = parser.advance() {
+            let command = command.map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {err}", file.name, parser.line()),
+                )
+            })?;
+            file.commands.push(command);
+            file.lines.push(parser.line());
+        }
+
+        Ok(file)
+    }
+
+    fn read_files(&self) -> Result<Vec<VmFile>, io::Error> {
+        self.files
+            .iter()
+            .map(|filepath| Self::read_file(filepath))
+            .collect()
+    }
+
+    /// Reads all files, removing unreachable functions if enabled
+    fn read_pruned(&self) -> Result<(Vec<VmFile>, PruneReport), io::Error> {
+        let mut files = self.read_files()?;
+        let report = if self.prune {
+            prune::prune(&mut files)
+        } else {
+            PruneReport::default()
+        };
+
+        Ok((files, report))
+    }
+
+    /// Static variables used by every class of the program
+    pub fn statics(&self) -> Result<StaticAllocation, io::Error> {
+        Ok(StaticAllocation::new(&self.read_pruned()?.0))
+    }
+
+    /// Functions and calls between them, after pruning if enabled
+    pub fn call_graph(&self) -> Result<CallGraph, io::Error> {
+        Ok(CallGraph::new(&self.read_pruned()?.0))
+    }
+
+    /// Functions which will be removed as unreachable, empty if pruning is disabled
+    pub fn pruned(&self) -> Result<PruneReport, io::Error> {
+        Ok(self.read_pruned()?.1)
+    }
+
+    /// Reads all files, then verifies, prunes and optimizes them if enabled.
+    /// Program whose statics do not fit into RAM 16-255 is always rejected
+    pub fn program(&self) -> Result<Vec<VmFile>, io::Error> {
+        let mut files = self.read_files()?;
+
+        let to_io_error = |errors: Vec<verifier::VerifyError>| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            io::Error::new(io::ErrorKind::InvalidData, errors.join("\n"))
+        };
+
+        verifier::check_duplicates(&files).map_err(to_io_error)?;
+        verifier::check_pop_constant(&files).map_err(to_io_error)?;
+        if !self.extensions {
+            verifier::check_extensions(&files).map_err(to_io_error)?;
+        }
+
+        // Verify whole program, so errors in unreachable code are reported too
+        if self.verify {
+            verifier::verify(&files).map_err(to_io_error)?;
+        }
+
+        if self.prune {
+            prune::prune(&mut files);
+        }
+
+        StaticAllocation::new(&files)
+            .check()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
 
-            Ok::<(), io::Error>(())
-        })?;
+        if self.optimize {
+            files.iter_mut().for_each(optimizer::optimize);
+        }
+
+        Ok(files)
+    }
+
+    /// Instructions generated for file, with index of their first command.
+    /// Pairs of commands are fused only when optimizing
+    fn instructions(&self, file: &VmFile) -> Vec<(usize, Instruction)> {
+        if self.optimize {
+            optimizer::fuse(&file.commands)
+        } else {
+            file.commands
+                .iter()
+                .cloned()
+                .map(Instruction::Command)
+                .enumerate()
+                .collect()
+        }
+    }
+
+    /// Writes VM commands as they will be translated, after optimization if enabled.
+    /// Fused pairs are preceded by `// move: ...` or `// if-not: ...` comment
+    pub fn emit_vm<T: Write>(&mut self, mut writer: T) -> Result<(), io::Error> {
+        for file in self.program()? {
+            writeln!(writer, "// File: {}", file.name)?;
+
+            for (_, instruction) in self.instructions(&file) {
+                if let Some(name) = instruction.fused_name() {
+                    writeln!(writer, "// {name}: {instruction}")?;
+                }
+                vm::write_program(&instruction.commands(), &mut writer)?;
+            }
+        }
 
         Ok(())
     }
+
+    /// Translates program into Hack assembly, returning map from VM commands
+    /// to lines of generated assembly
+    pub fn emit<T: Write>(&mut self, writer: T) -> Result<SourceMap, io::Error> {
+        self.emit_with(&mut HackGenerator::new(self.compact), writer)
+    }
+
+    /// Translates program and assembles it in memory, writing Hack machine code
+    pub fn emit_hack<T: Write>(&mut self, writer: T) -> Result<(), io::Error> {
+        let mut asm = vec![];
+        self.emit(&mut asm)?;
+        let asm = String::from_utf8(asm).map_err(io::Error::other)?;
+
+        Assembler::new(&asm).write(writer)
+    }
+
+    /// Translates program with provided backend
+    pub fn emit_with<G: CodeGenerator, T: Write>(
+        &mut self,
+        generator: &mut G,
+        writer: T,
+    ) -> Result<SourceMap, io::Error> {
+        let program = self.program()?;
+        let mut writer = LineCounter::new(writer);
+        let mut map = SourceMap::default();
+
+        generator.bootstrap(&mut writer)?;
+
+        for file in &program {
+            generator.start_file(file, &mut writer)?;
+
+            for (index, instruction) in &self.instructions(file) {
+                let line = file.line(*index);
+                let text = instruction.to_string();
+
+                if self.annotate {
+                    let location = match line {
+                        Some(line) => format!("{}:{line}", file.name),
+                        None => file.name.clone(),
+                    };
+                    generator.comment(&format!("{location}: {text}"), &mut writer)?;
+                }
+
+                let start = writer.lines() + 1;
+                generator.write_instruction(instruction, &mut writer)?;
+                map.entries.push(MapEntry {
+                    file: file.name.clone(),
+                    line,
+                    command: text,
+                    output: start..=writer.lines(),
+                });
+            }
+        }
+
+        generator.finish(&mut writer)?;
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn output_does_not_depend_on_input_order() {
+        let dir = std::env::temp_dir().join(format!("translator-order-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let sources = [
+            (
+                "Main.vm",
+                "function Main.main 0\npush static 0\ncall Util.inc 1\nreturn",
+            ),
+            (
+                "Util.vm",
+                "function Util.inc 0\npush argument 0\npush constant 1\nadd\nreturn",
+            ),
+            (
+                "Sys.vm",
+                "function Sys.init 0\ncall Main.main 0\npop static 1\nlabel END\ngoto END",
+            ),
+        ];
+        let paths: Vec<PathBuf> = sources
+            .iter()
+            .map(|(name, source)| {
+                let path = dir.join(name);
+                fs::write(&path, source).unwrap();
+                path
+            })
+            .collect();
+
+        let emit = |order: [usize; 3]| {
+            let mut buf = vec![];
+            Translator::from_files(order.iter().map(|i| paths[*i].clone()).collect())
+                .emit(&mut buf)
+                .unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+
+        let expected = emit([0, 1, 2]);
+        for order in [[0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]] {
+            assert!(emit(order) == expected, "order {order:?} changed output");
+        }
+        assert!(expected.find("// File: Sys.vm") < expected.find("// File: Main.vm"));
+        assert!(expected.find("// File: Main.vm") < expected.find("// File: Util.vm"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn program_and_earlier_libraries_take_precedence() {
+        let dir = std::env::temp_dir().join(format!("translator-library-{}", std::process::id()));
+        let sources = [
+            (
+                "program/Sys.vm",
+                "function Sys.init 0\ncall Main.main 0\ncall Util.get 0\nlabel END\ngoto END",
+            ),
+            (
+                "program/Main.vm",
+                "function Main.main 0\npush constant 1\nreturn",
+            ),
+            (
+                "first/Main.vm",
+                "function Main.main 0\npush constant 2\nreturn",
+            ),
+            (
+                "first/Util.vm",
+                "function Util.get 0\npush constant 3\nreturn",
+            ),
+            (
+                "second/Util.vm",
+                "function Util.get 0\npush constant 4\nreturn",
+            ),
+            ("second/Sys.vm", "function Sys.init 0\nreturn"),
+            (
+                "second/Math.vm",
+                "function Math.abs 0\npush argument 0\nreturn",
+            ),
+        ];
+        for (name, source) in sources {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        let mut translator = Translator::new(&dir.join("program"))
+            .unwrap()
+            .library(&dir.join("first"))
+            .unwrap()
+            .library(&dir.join("second"))
+            .unwrap();
+        let files: Vec<PathBuf> = translator
+            .files
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+
+        assert_eq!(
+            files,
+            [
+                "program/Sys.vm",
+                "program/Main.vm",
+                "second/Math.vm",
+                "first/Util.vm"
+            ]
+            .map(PathBuf::from)
+        );
+        // Functions of skipped files are not duplicates
+        translator.emit(vec![]).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn source_is_preferred_to_binary_module() {
+        let dir = std::env::temp_dir().join(format!("translator-vmb-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sys = "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel END\ngoto END";
+        fs::write(dir.join("Sys.vm"), sys).unwrap();
+        fs::write(
+            dir.join("Main.vm"),
+            "function Main.main 0\npush constant 0\nreturn",
+        )
+        .unwrap();
+        let module = VmFile::new("Sys.vm", vm::parse_program(sys).unwrap());
+        bytecode::write_module(&module, File::create(dir.join("Sys.vmb")).unwrap()).unwrap();
+
+        let mut translator = Translator::new(&dir).unwrap();
+        assert_eq!(translator.files, [dir.join("Sys.vm"), dir.join("Main.vm")]);
+        translator.emit(vec![]).unwrap();
+
+        // Binary module alone is used and still goes first
+        fs::remove_file(dir.join("Sys.vm")).unwrap();
+        let mut translator = Translator::new(&dir).unwrap();
+        assert_eq!(translator.files, [dir.join("Sys.vmb"), dir.join("Main.vm")]);
+        translator.emit(vec![]).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pop_constant_is_rejected_without_verification() {
+        let dir = std::env::temp_dir().join(format!("translator-pop-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Sys.vm");
+        fs::write(
+            &path,
+            "function Sys.init 0\npush constant 1\npop constant 2",
+        )
+        .unwrap();
+
+        let mut translator = Translator::from_files(vec![path]).verify(false);
+        let hack = translator.emit(vec![]).unwrap_err();
+        let c = translator
+            .emit_with(&mut crate::codegen::c::CGenerator::new(), vec![])
+            .unwrap_err();
+
+        for error in [hack, c] {
+            assert_eq!(error.to_string(), "Sys.vm:3: `pop constant` is not allowed");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn optimized_source_map_keeps_lines() {
+        let dir = std::env::temp_dir().join(format!("translator-map-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Sys.vm");
+        fs::write(
+            &path,
+            "function Sys.init 0\n// constant\npush constant 1\npush constant 2\nadd\npop static 0\nlabel END\ngoto END",
+        )
+        .unwrap();
+
+        let map = Translator::from_files(vec![path])
+            .optimize(true)
+            .emit(vec![])
+            .unwrap();
+        let lines: Vec<(Option<usize>, &str)> = map
+            .entries
+            .iter()
+            .map(|entry| (entry.line, entry.command.as_str()))
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                (Some(1), "function Sys.init 0"),
+                (Some(3), "push constant 3; pop static 0"),
+                (Some(7), "label END"),
+                (Some(8), "goto END"),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
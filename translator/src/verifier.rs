@@ -0,0 +1,473 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::vm::{ArtithmeticOperation, Segment, VmCommand, VmFile};
+
+/// Problem found in VM program before translation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Function definition found in program
+struct FunctionInfo<'a> {
+    file: &'a VmFile,
+    /// Number of arguments function reads, i.e. highest `argument` index + 1
+    used_args: u16,
+}
+
+/// Checks whole program:
+/// * stack never underflows and has the same depth whenever a label is reached
+/// * every called function is defined in the file of its class with enough arguments
+/// * `pop constant` is not used and `pointer`/`temp` indices are in range
+///
+/// Returns all found errors ordered by file and command
+pub fn verify(files: &[VmFile]) -> Result<(), Vec<VerifyError>> {
+    let mut functions = HashMap::new();
+    for file in files {
+        for (name, body) in function_bodies(&file.commands) {
+            if let Some(name) = name {
+                functions.insert(
+                    name,
+                    FunctionInfo {
+                        file,
+                        used_args: used_args(body),
+                    },
+                );
+            }
+        }
+    }
+
+    let mut errors = vec![];
+    for file in files {
+        let mut file_errors = vec![];
+        let mut offset = 0;
+
+        for (_, body) in function_bodies(&file.commands) {
+            check_commands(body, offset, &functions, &mut file_errors);
+            check_stack(body, offset, &mut file_errors);
+            offset += body.len();
+        }
+
+        file_errors.sort_by_key(|(index, _)| *index);
+        errors.extend(file_errors.into_iter().map(|(index, message)| VerifyError {
+            file: file.name.clone(),
+            line: file.line(index),
+            message,
+        }));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that every function is defined only once in the whole program.
+/// Unlike `verify`, this is always done, since assembler would silently
+/// use the last definition
+pub fn check_duplicates(files: &[VmFile]) -> Result<(), Vec<VerifyError>> {
+    let mut defined: HashMap<&str, &str> = HashMap::new();
+    let mut errors = vec![];
+
+    for file in files {
+        for (index, command) in file.commands.iter().enumerate() {
+            if let VmCommand::Function { name, .. } = command {
+                if let Some(first) = defined.insert(name, &file.name) {
+                    errors.push(VerifyError {
+                        file: file.name.clone(),
+                        line: file.line(index),
+                        message: format!("function `{name}` is already defined in `{first}`"),
+                    });
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that program uses no VM extensions (`mul`, `div`, `mod`),
+/// done unless extensions are enabled
+pub fn check_extensions(files: &[VmFile]) -> Result<(), Vec<VerifyError>> {
+    let errors: Vec<VerifyError> = files
+        .iter()
+        .flat_map(|file| {
+            file.commands
+                .iter()
+                .enumerate()
+                .filter_map(move |(index, command)| match command {
+                    VmCommand::Arithmetic(op) if op.is_extension() => Some(VerifyError {
+                        file: file.name.clone(),
+                        line: file.line(index),
+                        message: format!("`{op}` is a VM extension, which is not enabled"),
+                    }),
+                    _ => None,
+                })
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that program has no `pop constant`, which no backend can translate.
+/// Unlike `verify`, this is always done
+pub fn check_pop_constant(files: &[VmFile]) -> Result<(), Vec<VerifyError>> {
+    let errors: Vec<VerifyError> = files
+        .iter()
+        .flat_map(|file| {
+            file.commands
+                .iter()
+                .enumerate()
+                .filter_map(move |(index, command)| match command {
+                    VmCommand::Pop {
+                        segment: Segment::Constant,
+                        ..
+                    } => Some(VerifyError {
+                        file: file.name.clone(),
+                        line: file.line(index),
+                        message: "`pop constant` is not allowed".to_owned(),
+                    }),
+                    _ => None,
+                })
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Splits commands into function bodies, each starting with its `function` command.
+/// Commands before first function have no name
+pub(crate) fn function_bodies(commands: &[VmCommand]) -> Vec<(Option<&str>, &[VmCommand])> {
+    let mut bodies = vec![];
+    let mut start = 0;
+
+    for (index, command) in commands.iter().enumerate().skip(1) {
+        if let VmCommand::Function { .. } = command {
+            bodies.push(&commands[start..index]);
+            start = index;
+        }
+    }
+    bodies.push(&commands[start..]);
+
+    bodies
+        .into_iter()
+        .filter(|body| !body.is_empty())
+        .map(|body| match &body[0] {
+            VmCommand::Function { name, .. } => (Some(name.as_str()), body),
+            _ => (None, body),
+        })
+        .collect()
+}
+
+fn used_args(body: &[VmCommand]) -> u16 {
+    body.iter()
+        .filter_map(|command| match command {
+            VmCommand::Push {
+                segment: Segment::Argument,
+                index,
+            }
+            | VmCommand::Pop {
+                segment: Segment::Argument,
+                index,
+            } => Some(index + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn check_segment(segment: &Segment, index: u16) -> Option<String> {
+    match segment {
+        Segment::Pointer if index > 1 => Some(format!("pointer index {index} is out of range 0-1")),
+        Segment::Temp if index > 7 => Some(format!("temp index {index} is out of range 0-7")),
+        _ => None,
+    }
+}
+
+/// Checks commands which can be verified one by one
+fn check_commands(
+    body: &[VmCommand],
+    offset: usize,
+    functions: &HashMap<&str, FunctionInfo>,
+    errors: &mut Vec<(usize, String)>,
+) {
+    for (index, command) in body.iter().enumerate() {
+        let index = index + offset;
+
+        match command {
+            VmCommand::Pop {
+                segment: Segment::Constant,
+                ..
+            } => errors.push((index, "`pop constant` is not allowed".to_owned())),
+            VmCommand::Push { segment, index: i } | VmCommand::Pop { segment, index: i } => {
+                if let Some(message) = check_segment(segment, *i) {
+                    errors.push((index, message));
+                }
+            }
+            VmCommand::Call { name, args } => match functions.get(name.as_str()) {
+                None => errors.push((index, format!("call to undefined function `{name}`"))),
+                Some(info) => {
+                    let module = name.split_once('.').map_or(name.as_str(), |(m, _)| m);
+                    if info.file.module() != module {
+                        errors.push((
+                            index,
+                            format!(
+                                "function `{name}` is defined in `{}`, expected in `{module}.vm`",
+                                info.file.name
+                            ),
+                        ));
+                    }
+
+                    if *args < info.used_args {
+                        errors.push((
+                            index,
+                            format!(
+                                "`{name}` uses {} arguments, but is called with {args}",
+                                info.used_args
+                            ),
+                        ));
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Number of values command needs on stack and how it changes stack depth
+fn stack_effect(command: &VmCommand) -> (i32, i32) {
+    use ArtithmeticOperation::*;
+    use VmCommand::*;
+
+    match command {
+        Push { .. } => (0, 1),
+        Pop { .. } | If(_) => (1, -1),
+        Arithmetic(Neg | Not) => (1, 0),
+        Arithmetic(_) => (2, -1),
+        Call { args, .. } => (*args as i32, 1 - *args as i32),
+        Return => (1, -1),
+        Label(_) | Goto(_) | Function { .. } => (0, 0),
+    }
+}
+
+/// Follows every path through function body, tracking stack depth
+/// relative to the start of the function
+fn check_stack(body: &[VmCommand], offset: usize, errors: &mut Vec<(usize, String)>) {
+    let mut labels = HashMap::new();
+    for (index, command) in body.iter().enumerate() {
+        if let VmCommand::Label(label) = command {
+            if labels.insert(label.as_str(), index).is_some() {
+                errors.push((index + offset, format!("label `{label}` is defined twice")));
+            }
+        }
+    }
+
+    let mut depths: Vec<Option<i32>> = vec![None; body.len()];
+    let mut reported = vec![false; body.len()];
+    let mut queue = vec![(0, 0)];
+
+    while let Some((index, depth)) = queue.pop() {
+        if index >= body.len() {
+            continue;
+        }
+
+        match depths[index] {
+            Some(known) if known != depth => {
+                if !reported[index] {
+                    reported[index] = true;
+                    let place = match &body[index] {
+                        VmCommand::Label(label) => format!("label `{label}`"),
+                        command => format!("`{command}`"),
+                    };
+                    errors.push((
+                        index + offset,
+                        format!("inconsistent stack depth at {place}: {known} and {depth}"),
+                    ));
+                }
+                continue;
+            }
+            Some(_) => continue,
+            None => depths[index] = Some(depth),
+        }
+
+        let command = &body[index];
+        let (required, delta) = stack_effect(command);
+        if depth < required {
+            errors.push((
+                index + offset,
+                format!("stack underflow: `{command}` needs {required} values, stack has {depth}"),
+            ));
+            continue;
+        }
+        let depth = depth + delta;
+
+        let mut jump = |label: &str| match labels.get(label) {
+            Some(target) => queue.push((*target, depth)),
+            None => errors.push((
+                index + offset,
+                format!("label `{label}` is not defined in this function"),
+            )),
+        };
+
+        match command {
+            VmCommand::Goto(label) => jump(label),
+            VmCommand::If(label) => {
+                jump(label);
+                queue.push((index + 1, depth));
+            }
+            VmCommand::Return => {}
+            _ => queue.push((index + 1, depth)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_program;
+
+    fn verify_str(files: &[(&str, &str)]) -> Vec<String> {
+        let files: Vec<VmFile> = files
+            .iter()
+            .map(|(name, source)| VmFile::new(*name, parse_program(source).unwrap()))
+            .collect();
+
+        match verify(&files) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.message.clone()).collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_program() {
+        let errors = verify_str(&[
+            (
+                "Main.vm",
+                "function Main.max 0\npush argument 0\npush argument 1\ngt\nif-goto A\npush argument 1\nreturn\nlabel A\npush argument 0\nreturn",
+            ),
+            (
+                "Sys.vm",
+                "function Sys.init 0\npush constant 1\npush constant 2\ncall Main.max 2\npop temp 0\nlabel L\ngoto L",
+            ),
+        ]);
+
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn detects_underflow_and_inconsistent_labels() {
+        let errors = verify_str(&[(
+            "Main.vm",
+            "function Main.f 0\npush constant 1\nif-goto L\npush constant 2\nlabel L\nadd\nreturn",
+        )]);
+
+        assert_eq!(
+            errors,
+            vec![
+                "inconsistent stack depth at label `L`: 1 and 0",
+                "stack underflow: `add` needs 2 values, stack has 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_calls() {
+        let errors = verify_str(&[
+            ("Main.vm", "function Main.f 0\npush argument 1\nreturn\nfunction Other.g 0\npush constant 0\nreturn"),
+            (
+                "Sys.vm",
+                "function Sys.init 0\npush constant 1\ncall Main.f 1\ncall Other.g 0\ncall Nope.h 0\nlabel L\ngoto L",
+            ),
+        ]);
+
+        assert_eq!(
+            errors,
+            vec![
+                "`Main.f` uses 2 arguments, but is called with 1",
+                "function `Other.g` is defined in `Main.vm`, expected in `Other.vm`",
+                "call to undefined function `Nope.h`",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_functions() {
+        let files = [
+            VmFile::new(
+                "Math.vm",
+                parse_program("function Math.abs 0\nreturn").unwrap(),
+            ),
+            VmFile::new(
+                "Main.vm",
+                parse_program("function Math.abs 0\nreturn").unwrap(),
+            ),
+        ];
+
+        let errors = check_duplicates(&files).unwrap_err();
+
+        assert_eq!(
+            errors[0].to_string(),
+            "Main.vm: function `Math.abs` is already defined in `Math.vm`"
+        );
+    }
+
+    #[test]
+    fn rejects_disabled_extensions() {
+        let mut file = VmFile::new(
+            "Main.vm",
+            parse_program("push constant 6\npush constant 3\ndiv").unwrap(),
+        );
+        file.lines = vec![1, 2, 3];
+
+        let errors = check_extensions(&[file]).unwrap_err();
+
+        assert_eq!(
+            errors[0].to_string(),
+            "Main.vm:3: `div` is a VM extension, which is not enabled"
+        );
+    }
+
+    #[test]
+    fn checks_segments() {
+        let errors = verify_str(&[(
+            "Main.vm",
+            "push constant 1\npop constant 0\npush constant 1\npop temp 9\npush pointer 2",
+        )]);
+
+        assert_eq!(
+            errors,
+            vec![
+                "`pop constant` is not allowed",
+                "temp index 9 is out of range 0-7",
+                "pointer index 2 is out of range 0-1"
+            ]
+        );
+    }
+}
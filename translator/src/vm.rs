@@ -0,0 +1,442 @@
+//! VM language model shared by the translator and other tools.
+//!
+//! ```
+//! use translator::vm::{Segment, VmCommand};
+//!
+//! let command: VmCommand = "push local 2 // comment".parse().unwrap();
+//! assert_eq!(command, VmCommand::push(Segment::Local, 2));
+//! assert_eq!(command.to_string(), "push local 2");
+//! ```
+use core::fmt;
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// Single VM command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmCommand {
+    Arithmetic(ArtithmeticOperation),
+    Push {
+        segment: Segment,
+        index: u16,
+    },
+    Pop {
+        segment: Segment,
+        index: u16,
+    },
+    Label(String),
+    Goto(String),
+    /// `if-goto label`
+    If(String),
+    /// `function name locals`
+    Function {
+        name: String,
+        locals: u16,
+    },
+    Return,
+    /// `call name args`
+    Call {
+        name: String,
+        args: u16,
+    },
+}
+
+impl VmCommand {
+    pub fn arithmetic(op: ArtithmeticOperation) -> Self {
+        VmCommand::Arithmetic(op)
+    }
+
+    pub fn push(segment: Segment, index: u16) -> Self {
+        VmCommand::Push { segment, index }
+    }
+
+    pub fn pop(segment: Segment, index: u16) -> Self {
+        VmCommand::Pop { segment, index }
+    }
+
+    pub fn label(label: impl Into<String>) -> Self {
+        VmCommand::Label(label.into())
+    }
+
+    pub fn goto(label: impl Into<String>) -> Self {
+        VmCommand::Goto(label.into())
+    }
+
+    pub fn if_goto(label: impl Into<String>) -> Self {
+        VmCommand::If(label.into())
+    }
+
+    /// `locals` - number of local variables
+    pub fn function(name: impl Into<String>, locals: u16) -> Self {
+        VmCommand::Function {
+            name: name.into(),
+            locals,
+        }
+    }
+
+    pub fn call(name: impl Into<String>, args: u16) -> Self {
+        VmCommand::Call {
+            name: name.into(),
+            args,
+        }
+    }
+}
+
+/// Writes command as canonical `.vm` text without trailing newline
+impl fmt::Display for VmCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use VmCommand::*;
+
+        match self {
+            Arithmetic(op) => write!(f, "{op}"),
+            Push { segment, index } => write!(f, "push {segment} {index}"),
+            Pop { segment, index } => write!(f, "pop {segment} {index}"),
+            Label(label) => write!(f, "label {label}"),
+            Goto(label) => write!(f, "goto {label}"),
+            If(label) => write!(f, "if-goto {label}"),
+            Function { name, locals } => write!(f, "function {name} {locals}"),
+            Return => write!(f, "return"),
+            Call { name, args } => write!(f, "call {name} {args}"),
+        }
+    }
+}
+
+/// Line of `.vm` source which is not a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotCommandError {
+    pub command: String,
+}
+
+impl fmt::Display for NotCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "supplied string is not a command: `{}`", self.command)
+    }
+}
+
+impl std::error::Error for NotCommandError {}
+
+impl NotCommandError {
+    fn new(command: &str) -> Self {
+        Self {
+            command: command.to_owned(),
+        }
+    }
+}
+
+/// Removes `//` comment and surrounding whitespaces
+pub(crate) fn strip_comment(line: &str) -> &str {
+    match line.split_once("//") {
+        Some((needed, _)) => needed.trim(),
+        None => line.trim(),
+    }
+}
+
+impl FromStr for VmCommand {
+    type Err = NotCommandError;
+
+    /// Parses single command. Trailing `//` comment is allowed
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        VmCommand::try_from(value)
+    }
+}
+
+impl TryFrom<&str> for VmCommand {
+    type Error = NotCommandError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let error = || NotCommandError::new(value);
+        let number = |word: Option<&str>| -> Result<u16, NotCommandError> {
+            word.ok_or_else(error)?.parse().map_err(|_| error())
+        };
+        let name = |word: Option<&str>| -> Result<String, NotCommandError> {
+            Ok(word.ok_or_else(error)?.to_owned())
+        };
+
+        let mut words = strip_comment(value).split_whitespace();
+        let command = match words.next().ok_or_else(error)? {
+            op if ArtithmeticOperation::try_from(op).is_ok() => {
+                VmCommand::Arithmetic(ArtithmeticOperation::try_from(op)?)
+            }
+            command @ ("push" | "pop") => {
+                let segment =
+                    Segment::try_from(words.next().ok_or_else(error)?).map_err(|_| error())?;
+                let index = number(words.next())?;
+
+                if command == "push" {
+                    VmCommand::Push { segment, index }
+                } else {
+                    VmCommand::Pop { segment, index }
+                }
+            }
+            "label" => VmCommand::Label(name(words.next())?),
+            "goto" => VmCommand::Goto(name(words.next())?),
+            "if-goto" => VmCommand::If(name(words.next())?),
+            "function" => VmCommand::Function {
+                name: name(words.next())?,
+                locals: number(words.next())?,
+            },
+            "call" => VmCommand::Call {
+                name: name(words.next())?,
+                args: number(words.next())?,
+            },
+            "return" => VmCommand::Return,
+            _ => return Err(error()),
+        };
+
+        if words.next().is_some() {
+            return Err(error());
+        }
+
+        Ok(command)
+    }
+}
+
+/// Error in `.vm` program with 1-based line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub error: NotCommandError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses whole `.vm` source skipping comments and empty lines
+pub fn parse_program(source: &str) -> Result<Vec<VmCommand>, ParseError> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !strip_comment(line).is_empty())
+        .map(|(number, line)| {
+            VmCommand::try_from(line).map_err(|error| ParseError {
+                line: number + 1,
+                error,
+            })
+        })
+        .collect()
+}
+
+/// Writes commands as canonical `.vm` text, one command per line
+pub fn write_program<'a, W, I>(commands: I, mut writer: W) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a VmCommand>,
+{
+    commands
+        .into_iter()
+        .try_for_each(|command| writeln!(writer, "{command}"))
+}
+
+/// Commands of a single `.vm` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmFile {
+    /// File name, e.g. `Main.vm`
+    pub name: String,
+    pub commands: Vec<VmCommand>,
+    /// Source line of every command, empty if unknown
+    pub lines: Vec<usize>,
+}
+
+impl VmFile {
+    pub fn new(name: impl Into<String>, commands: Vec<VmCommand>) -> Self {
+        Self {
+            name: name.into(),
+            commands,
+            lines: vec![],
+        }
+    }
+
+    /// File name without extension, which is also the class (module) name
+    pub fn module(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map_or(self.name.as_str(), |(stem, _)| stem)
+    }
+
+    /// Source line of command with `index`, if known
+    pub fn line(&self, index: usize) -> Option<usize> {
+        self.lines.get(index).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtithmeticOperation {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    /// Extension: signed multiplication, wraps on overflow
+    Mul,
+    /// Extension: signed division rounding toward zero, `x div 0` is 0
+    Div,
+    /// Extension: remainder of `div` with the sign of dividend, `x mod 0` is `x`
+    Mod,
+}
+
+impl ArtithmeticOperation {
+    /// Operation is not part of standard VM language and must be enabled explicitly
+    pub fn is_extension(&self) -> bool {
+        matches!(
+            self,
+            ArtithmeticOperation::Mul | ArtithmeticOperation::Div | ArtithmeticOperation::Mod
+        )
+    }
+}
+
+impl fmt::Display for ArtithmeticOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ArtithmeticOperation::*;
+
+        let name = match self {
+            Add => "add",
+            Sub => "sub",
+            Neg => "neg",
+            Eq => "eq",
+            Gt => "gt",
+            Lt => "lt",
+            And => "and",
+            Or => "or",
+            Not => "not",
+            Mul => "mul",
+            Div => "div",
+            Mod => "mod",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl TryFrom<&str> for ArtithmeticOperation {
+    type Error = NotCommandError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "add" => Ok(ArtithmeticOperation::Add),
+            "sub" => Ok(ArtithmeticOperation::Sub),
+            "neg" => Ok(ArtithmeticOperation::Neg),
+            "eq" => Ok(ArtithmeticOperation::Eq),
+            "gt" => Ok(ArtithmeticOperation::Gt),
+            "lt" => Ok(ArtithmeticOperation::Lt),
+            "and" => Ok(ArtithmeticOperation::And),
+            "or" => Ok(ArtithmeticOperation::Or),
+            "not" => Ok(ArtithmeticOperation::Not),
+            "mul" => Ok(ArtithmeticOperation::Mul),
+            "div" => Ok(ArtithmeticOperation::Div),
+            "mod" => Ok(ArtithmeticOperation::Mod),
+            _ => Err(NotCommandError::new(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+impl TryFrom<&str> for Segment {
+    type Error = NotCommandError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        use self::Segment::*;
+
+        match value {
+            "argument" => Ok(Argument),
+            "local" => Ok(Local),
+            "static" => Ok(Static),
+            "constant" => Ok(Constant),
+            "this" => Ok(This),
+            "that" => Ok(That),
+            "pointer" => Ok(Pointer),
+            "temp" => Ok(Temp),
+            _ => Err(NotCommandError::new(value)),
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Segment::*;
+
+        let name = match self {
+            Argument => "argument",
+            Local => "local",
+            Static => "static",
+            Constant => "constant",
+            This => "this",
+            That => "that",
+            Pointer => "pointer",
+            Temp => "temp",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write_round_trip() {
+        let source = "function Main.main 1\n// comment\n\npush constant 7\n  pop local 0 // trailing\nif-goto END\ncall Math.multiply 2\nreturn";
+        let commands = parse_program(source).unwrap();
+
+        assert_eq!(
+            commands,
+            vec![
+                VmCommand::function("Main.main", 1),
+                VmCommand::push(Segment::Constant, 7),
+                VmCommand::pop(Segment::Local, 0),
+                VmCommand::if_goto("END"),
+                VmCommand::call("Math.multiply", 2),
+                VmCommand::Return,
+            ]
+        );
+
+        let mut buf = vec![];
+        write_program(&commands, &mut buf).unwrap();
+        assert_eq!(
+            parse_program(&String::from_utf8(buf).unwrap()).unwrap(),
+            commands
+        );
+    }
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        let error = parse_program("push constant 1\n\npush nowhere 1").unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.error.command, "push nowhere 1");
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        for command in [
+            "push local",
+            "pop local x",
+            "call Main.main",
+            "add 1",
+            "jump L",
+        ] {
+            assert!(command.parse::<VmCommand>().is_err(), "{command}");
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::{fs, process::Command};
+
+#[test]
+fn optimized_vm_dump_shows_fused_instructions() {
+    let dir = std::env::temp_dir().join(format!("translator-cli-dump-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("Sys.vm");
+    fs::write(
+        &path,
+        "function Sys.init 0\npush constant 1\npop static 0\npush static 0\nnot\nif-goto END\nlabel END\ngoto END",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_translator"))
+        .arg("--emit-optimized-vm")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let dump = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        dump.contains("// move: push constant 1; pop static 0\npush constant 1\npop static 0\n"),
+        "{dump}"
+    );
+    assert!(
+        dump.contains("// if-not: not; if-goto END\nnot\nif-goto END\n"),
+        "{dump}"
+    );
+}